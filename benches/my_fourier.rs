@@ -1,10 +1,44 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use cldj::transform::fourier_transform;
+use num::Complex;
+use std::f64::consts::PI;
+
+/// The textbook O(N²) transform, as a baseline for the FFT. The twiddle
+/// factors are tabulated once so the loop is multiplies and adds.
+fn naive_dft(signal: &[f64]) -> Vec<Complex<f64>> {
+    let n = signal.len();
+    let twiddles: Vec<Complex<f64>> = (0..n)
+        .map(|k| Complex::from_polar(1.0, -2.0 * PI * k as f64 / n as f64))
+        .collect();
+    (0..n)
+        .map(|k| {
+            signal
+                .iter()
+                .enumerate()
+                .map(|(t, &x)| twiddles[k * t % n] * x)
+                .sum()
+        })
+        .collect()
+}
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("fft", |b| {
         b.iter(|| fourier_transform(black_box(vec![1, 0, 0, 0, 0, 0, 0, 0])))
     });
+
+    // a power of two, and a tenth of a second at 44.1 kHz, which is not
+    let mut group = c.benchmark_group("fft sizes");
+    group.sample_size(10);
+    for &size in &[4096, 4410] {
+        let signal: Vec<f64> = (0..size).map(|n| (n as f64 * 0.1).sin()).collect();
+        group.bench_with_input(BenchmarkId::new("fft", size), &signal, |b, signal| {
+            b.iter(|| fourier_transform(black_box(signal.clone())))
+        });
+        group.bench_with_input(BenchmarkId::new("naive dft", size), &signal, |b, signal| {
+            b.iter(|| naive_dft(black_box(signal)))
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,28 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use cldj::transform::{Stft, Window};
+use std::thread;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    // three minutes of a sweep at 44.1 kHz
+    let signal: Vec<f64> = (0..3 * 60 * 44100)
+        .map(|n| (n as f64 * (0.01 + n as f64 * 1e-9)).sin())
+        .collect();
+    let stft = Stft::new(2048, 512, Window::Hann);
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut group = c.benchmark_group("stft threads");
+    group.sample_size(10);
+    let mut counts = vec![1, cores];
+    counts.dedup();
+    for nthreads in counts {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(nthreads),
+            &nthreads,
+            |b, &nthreads| b.iter(|| stft.process_parallel(black_box(&signal), nthreads)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -21,7 +21,11 @@ use std::time::Duration;
 
 use termion::input::TermRead;
 
-use super::transform::fourier_transform;
+use super::io::sample::ChannelSelection;
+use super::io::AudioSource;
+use super::transform::window::{self, Window};
+use super::transform::{spectrum_len, FftPlanner};
+use num::Complex;
 
 
 
@@ -56,6 +60,12 @@ impl Default for Config {
     }
 }
 
+impl Default for Events {
+    fn default() -> Events {
+        Events::new()
+    }
+}
+
 impl Events {
     pub fn new() -> Events {
         Events::with_config(Config::default())
@@ -69,15 +79,13 @@ impl Events {
             let ignore_exit_key = ignore_exit_key.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
-                        }
-                        if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                            return;
-                        }
+                for key in stdin.keys().flatten() {
+                    if let Err(err) = tx.send(Event::Input(key)) {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                    if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
+                        return;
                     }
                 }
             })
@@ -110,73 +118,141 @@ impl Events {
 }
 
 
+/// Number of frames shown in the signal chart and transformed for the bar chart.
+const WINDOW_SIZE: usize = 200;
+/// Taper applied to the frames before they are transformed.
+const WINDOW_FUNCTION: Window = Window::Hann;
+/// Number of frames the window advances each tick.
+const STEP: usize = 5;
+/// Number of points stacked to draw each cue marker as a vertical line.
+const MARKER_HEIGHT: usize = 20;
+
 struct App {
-    signal: Vec<(f64, f64)>,
+    source: Box<dyn AudioSource>,
+    selection: ChannelSelection,
     signal_buf: Vec<(f64, f64)>,
     window: [f64; 2],
     frequency: Vec<(String, u64)>,
+    planner: FftPlanner,
+    /// the window being transformed and its spectrum, kept to avoid
+    /// allocating every tick
+    samples: Vec<f64>,
+    spectrum: Vec<Complex<f64>>,
+    /// coefficients of `WINDOW_FUNCTION` as long as `samples`
+    taper: Vec<f64>,
+    /// frames holding a cue point or a loop boundary, in order
+    cues: Vec<f64>,
     max: f64,
     min: f64,
 }
 
 impl fmt::Display for App {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let signal_head = &self.signal[..2];
-        let signal_tail = &self.signal[(self.signal.len()-2)..];
         let signal_buf_head = &self.signal_buf[..2];
         let signal_buf_tail = &self.signal_buf[(self.signal_buf.len()-2)..];
         write!(
             f,
-            "App:\n  signal_head: {:?} ... {:?},\n  data_head: {:?} ... {:?},\n  window: {:?}",
-            signal_head, signal_tail, signal_buf_head, signal_buf_tail, self.window
+            "App:\n  data_head: {:?} ... {:?},\n  window: {:?}",
+            signal_buf_head, signal_buf_tail, self.window
         )
     }
 }
 
 impl App {
-    fn new(data: Vec<i16>) -> App {
-        let max = *data.iter().max().expect("could not get max") as f64;
-        let min = *data.iter().min().expect("could not get min") as f64;
-        let mut signal: Vec<(f64, f64)> = data
+    fn new(source: Box<dyn AudioSource>, selection: ChannelSelection) -> Result<App, Box<dyn Error>> {
+        let cue_points = source.cues();
+        let mut cues: Vec<f64> = cue_points
+            .points
             .iter()
-            .enumerate()
-            .map(|(i, x)| (i as f64, *x as f64))
-            .collect();
-        let signal_buf = signal.drain(..200).collect::<Vec<(f64, f64)>>();
-        let freq = fourier_transform(data);
-        let _f2: Vec<(String, u64)> = freq
-            .iter()
-            .enumerate()
-            .map(|(i, f)| (i.to_string(), f.norm() as u64))
+            .map(|point| point.position)
+            .chain(cue_points.loops.iter().flat_map(|l| vec![l.start, l.end]))
+            .map(f64::from)
             .collect();
+        cues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut app = App {
+            source,
+            selection,
+            signal_buf: Vec::new(),
+            window: [0.0, 100.0],
+            frequency: Vec::new(),
+            planner: FftPlanner::new(),
+            samples: Vec::with_capacity(WINDOW_SIZE),
+            spectrum: Vec::with_capacity(spectrum_len(WINDOW_SIZE)),
+            taper: Vec::new(),
+            cues,
+            // samples are normalized so full scale is known up front
+            max: 1.0,
+            min: -1.0,
+        };
+        app.pull(WINDOW_SIZE)?;
+        app.update_frequency();
+        Ok(app)
+    }
+
+    /// Appends up to `nframes` frames from the source to `signal_buf`,
+    /// returning how many were read.
+    fn pull(&mut self, nframes: usize) -> Result<usize, Box<dyn Error>> {
+        let start = self.source.position() as usize;
+        let nchannels = self.source.nchannels();
+        let mut buf = vec![0.0; nframes * nchannels];
+        let read = self.source.read_frames(&mut buf)?;
+        let selection = self.selection;
+        self.signal_buf.extend(
+            buf.chunks_exact(nchannels)
+                .take(read)
+                .enumerate()
+                .map(|(i, frame)| ((start + i) as f64, selection.pick(frame))),
+        );
+        Ok(read)
+    }
 
-        let frequency: Vec<(String, u64)> = freq
+    fn update_frequency(&mut self) {
+        let len = self.signal_buf.len();
+        if self.taper.len() != len {
+            self.taper = WINDOW_FUNCTION.coefficients(len);
+        }
+        self.samples.clear();
+        self.samples
+            .extend(self.signal_buf.iter().zip(&self.taper).map(|((_, x), w)| x * w));
+        self.spectrum.resize(spectrum_len(len), Complex::new(0.0, 0.0));
+        self.planner
+            .plan_real_forward(len)
+            .process(&self.samples, &mut self.spectrum);
+        window::calibrate(&mut self.spectrum, &self.taper);
+        // bars in percent of full scale
+        self.frequency = self
+            .spectrum
             .iter()
             .enumerate()
-            .map(|(i, f)| (i.to_string(), f.norm() as u64))
+            .map(|(i, f)| (i.to_string(), (f.norm() * 100.0).round() as u64))
             .collect();
+    }
 
-        App {
-            signal,
-            signal_buf,
-            window: [0.0, 100.0],
-            frequency: frequency,
-            max,
-            min,
-        }
+    /// Vertical lines at the cue markers inside the window.
+    fn cue_markers(&self) -> Vec<(f64, f64)> {
+        let min = self.min;
+        let step = (self.max - self.min) / MARKER_HEIGHT as f64;
+        self.cues
+            .iter()
+            .filter(|&&x| x >= self.window[0] && x <= self.window[1])
+            .flat_map(|&x| (0..=MARKER_HEIGHT).map(move |n| (x, min + n as f64 * step)))
+            .collect()
     }
 
-    fn update(&mut self) {
-        for _ in 0..5 {
-            self.signal_buf.remove(0);
-        }
-        self.signal_buf.extend(self.signal.drain(..5));
-        self.window[0] += 5.0;
-        self.window[1] += 5.0;
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        let pulled = self.pull(STEP)?;
+        self.signal_buf.drain(..pulled);
+        self.window[0] += pulled as f64;
+        self.window[1] += pulled as f64;
+        self.update_frequency();
+        Ok(())
     }
 }
 
-pub fn run(signal: Vec<i16>) -> Result<(), Box<dyn Error>> {
+pub fn run(
+    source: Box<dyn AudioSource>,
+    selection: ChannelSelection,
+) -> Result<(), Box<dyn Error>> {
     let stdout = io::stdout().into_raw_mode()?;
     let stdout = MouseTerminal::from(stdout);
     let stdout = AlternateScreen::from(stdout);
@@ -186,7 +262,7 @@ pub fn run(signal: Vec<i16>) -> Result<(), Box<dyn Error>> {
 
     let events = Events::new();
 
-    let mut app = App::new(signal);
+    let mut app = App::new(source, selection)?;
 
     loop {
         terminal.draw(|mut f| {
@@ -214,12 +290,18 @@ pub fn run(signal: Vec<i16>) -> Result<(), Box<dyn Error>> {
                 format!("{}", (app.min + app.max) / 2.0),
                 format!("{}", app.max),
             ];
+            let cue_markers = app.cue_markers();
             let datasets = [
                 Dataset::default()
                     .name("wav")
                     .marker(symbols::Marker::Dot)
                     .style(Style::default().fg(Color::Cyan))
                     .data(&app.signal_buf[..]),
+                Dataset::default()
+                    .name("cues")
+                    .marker(symbols::Marker::Braille)
+                    .style(Style::default().fg(Color::Magenta))
+                    .data(&cue_markers[..]),
             ];
             let chart = Chart::default()
                 .block(
@@ -263,7 +345,7 @@ pub fn run(signal: Vec<i16>) -> Result<(), Box<dyn Error>> {
                 }
             }
             Event::Tick => {
-                app.update();
+                app.update()?;
             }
         }
     }
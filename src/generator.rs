@@ -0,0 +1,162 @@
+use std::f64::consts::PI;
+
+/// The shape of one period of an oscillator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    /// rising from -1 to 1 over each period
+    Sawtooth,
+    Triangle,
+    /// white noise, which has no period
+    Noise,
+}
+
+impl Waveform {
+    /// The value at `phase` cycles into a period, for every waveform but
+    /// noise.
+    fn at(self, phase: f64) -> f64 {
+        let phase = phase - phase.floor();
+        match self {
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Noise => unreachable!(),
+        }
+    }
+}
+
+/// An endless stream of samples of a periodic signal.
+#[derive(Debug, Clone)]
+pub struct Oscillator {
+    pub waveform: Waveform,
+    /// cycles per second
+    pub frequency: f64,
+    /// the peak value
+    pub amplitude: f64,
+    /// the phase of the next sample, in cycles
+    pub phase: f64,
+    sample_rate: u32,
+    /// xorshift state for noise
+    seed: u32,
+}
+
+impl Oscillator {
+    /// A full scale oscillator at `frequency` Hz starting at phase 0.
+    pub fn new(waveform: Waveform, frequency: f64, sample_rate: u32) -> Oscillator {
+        Oscillator {
+            waveform,
+            frequency,
+            amplitude: 1.0,
+            phase: 0.0,
+            sample_rate,
+            seed: 0x9e37_79b9,
+        }
+    }
+
+    /// An oscillator with a period of `period` seconds, peaking at
+    /// `amplitude` and shifted `phase_shift` radians ahead.
+    pub fn with_period(
+        waveform: Waveform,
+        period: f64,
+        amplitude: f64,
+        phase_shift: f64,
+        sample_rate: u32,
+    ) -> Oscillator {
+        Oscillator {
+            amplitude,
+            phase: phase_shift / (2.0 * PI),
+            ..Oscillator::new(waveform, 1.0 / period, sample_rate)
+        }
+    }
+}
+
+impl Iterator for Oscillator {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let value = match self.waveform {
+            Waveform::Noise => {
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+                self.seed as f64 / u32::MAX as f64 * 2.0 - 1.0
+            }
+            waveform => waveform.at(self.phase),
+        };
+        self.phase += self.frequency / self.sample_rate as f64;
+        self.phase -= self.phase.floor();
+        Some(self.amplitude * value)
+    }
+}
+
+/// `nsamples` samples of a signal with a period of `period` seconds, peaking
+/// at `amplitude` and shifted `phase_shift` radians ahead.
+pub fn generate(
+    waveform: Waveform,
+    period: f64,
+    amplitude: f64,
+    phase_shift: f64,
+    sample_rate: u32,
+    nsamples: usize,
+) -> Vec<f64> {
+    Oscillator::with_period(waveform, period, amplitude, phase_shift, sample_rate)
+        .take(nsamples)
+        .collect()
+}
+
+#[cfg(test)]
+mod generator_test {
+    use super::{generate, Oscillator, Waveform};
+    use std::f64::consts::PI;
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-9, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        // four samples a period
+        let period = 4.0 / 8000.0;
+        assert_close(
+            &generate(Waveform::Sine, period, 0.5, 0.0, 8000, 5),
+            &[0.0, 0.5, 0.0, -0.5, 0.0],
+        );
+        assert_close(
+            &generate(Waveform::Sine, period, 1.0, PI / 2.0, 8000, 4),
+            &[1.0, 0.0, -1.0, 0.0],
+        );
+        assert_close(
+            &generate(Waveform::Square, period, 1.0, 0.0, 8000, 4),
+            &[1.0, 1.0, -1.0, -1.0],
+        );
+        assert_close(
+            &generate(Waveform::Sawtooth, period, 1.0, 0.0, 8000, 4),
+            &[-1.0, -0.5, 0.0, 0.5],
+        );
+        assert_close(
+            &generate(Waveform::Triangle, period, 1.0, 0.0, 8000, 4),
+            &[-1.0, 0.0, 1.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let noise: Vec<f64> = Oscillator::new(Waveform::Noise, 0.0, 44100)
+            .take(10000)
+            .collect();
+        assert!(noise.iter().all(|x| x.abs() <= 1.0));
+        let mean = noise.iter().sum::<f64>() / noise.len() as f64;
+        assert!(mean.abs() < 0.05);
+    }
+}
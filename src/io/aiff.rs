@@ -0,0 +1,533 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+
+use super::error::ContainerError;
+use super::id3;
+use super::sample::{SampleFormat, Samples};
+use super::wav::{zstr, TrackMetadata, WAV};
+
+/// Errors reading or writing AIFF files.
+pub type AiffError = ContainerError;
+
+/// How the samples in the SSND chunk are encoded. Anything but `None` needs
+/// an AIFF-C file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// big endian integers
+    None,
+    /// little endian integers
+    Sowt,
+    /// big endian 32 bit floats
+    Fl32,
+    /// big endian 64 bit floats
+    Fl64,
+}
+
+impl Compression {
+    fn new(fourcc: &[u8]) -> Option<Compression> {
+        match fourcc {
+            b"NONE" | b"twos" => Some(Compression::None),
+            b"sowt" => Some(Compression::Sowt),
+            b"fl32" | b"FL32" => Some(Compression::Fl32),
+            b"fl64" | b"FL64" => Some(Compression::Fl64),
+            _ => None,
+        }
+    }
+
+    fn fourcc(self) -> &'static [u8; 4] {
+        match self {
+            Compression::None => b"NONE",
+            Compression::Sowt => b"sowt",
+            Compression::Fl32 => b"fl32",
+            Compression::Fl64 => b"fl64",
+        }
+    }
+
+    /// The human readable name AIFF-C stores next to the fourcc.
+    fn name(self) -> &'static str {
+        match self {
+            Compression::None => "not compressed",
+            Compression::Sowt => "",
+            Compression::Fl32 => "32-bit floating point",
+            Compression::Fl64 => "64-bit floating point",
+        }
+    }
+
+    /// The usual encoding for samples in `format`: floats as AIFF-C, integers
+    /// as plain AIFF.
+    pub fn for_format(format: SampleFormat) -> Compression {
+        match format {
+            SampleFormat::F32 => Compression::Fl32,
+            SampleFormat::F64 => Compression::Fl64,
+            _ => Compression::None,
+        }
+    }
+
+    /// The sample format holding `bits_per_sample` bit samples; integer
+    /// samples narrower than their container are stored left justified.
+    fn sample_format(self, bits_per_sample: u16) -> Option<SampleFormat> {
+        match (self, bits_per_sample) {
+            (Compression::Fl32, _) => Some(SampleFormat::F32),
+            (Compression::Fl64, _) => Some(SampleFormat::F64),
+            (_, 1..=8) => Some(SampleFormat::U8),
+            (_, 9..=16) => Some(SampleFormat::I16),
+            (_, 17..=24) => Some(SampleFormat::I24),
+            (_, 25..=32) => Some(SampleFormat::I32),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes an 80 bit IEEE 754 extended precision number, which AIFF uses for
+/// the sample rate.
+fn from_extended(bytes: &[u8; 10]) -> f64 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32;
+    let mut mantissa = [0u8; 8];
+    mantissa.copy_from_slice(&bytes[2..10]);
+    let mantissa = u64::from_be_bytes(mantissa);
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    if bytes[0] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Encodes `x` as an 80 bit extended precision number. Only zero and normal
+/// numbers are supported, which covers every sample rate.
+fn to_extended(x: f64) -> [u8; 10] {
+    let mut bytes = [0u8; 10];
+    if x == 0.0 {
+        return bytes;
+    }
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1023 + 16383;
+    // extended precision stores the integer bit explicitly
+    let mantissa = (1 << 63) | ((bits & ((1 << 52) - 1)) << 11);
+    let sign = if x < 0.0 { 0x8000 } else { 0 };
+    bytes[0..2].copy_from_slice(&(sign | exponent as u16).to_be_bytes());
+    bytes[2..10].copy_from_slice(&mantissa.to_be_bytes());
+    bytes
+}
+
+/// The fields of the COMM chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonChunk {
+    pub nchannels: u16,
+    pub nframes: u32,
+    pub bits_per_sample: u16,
+    pub sample_rate: f64,
+    pub compression: Compression,
+}
+
+impl CommonChunk {
+    fn new(bytes: &[u8], aifc: bool, offset: u64) -> Result<CommonChunk, AiffError> {
+        let size = if aifc { 22 } else { 18 };
+        if bytes.len() < size {
+            return Err(AiffError::Corrupt {
+                offset,
+                reason: format!(
+                    "COMM chunk is {} bytes, expected at least {}",
+                    bytes.len(),
+                    size
+                ),
+            });
+        }
+        let mut sample_rate = [0u8; 10];
+        sample_rate.copy_from_slice(&bytes[8..18]);
+        let bits_per_sample = u16::from_be_bytes([bytes[6], bytes[7]]);
+        let compression = if aifc {
+            Compression::new(&bytes[18..22]).ok_or_else(|| AiffError::Unsupported {
+                offset,
+                reason: format!(
+                    "compression {:?} with {} bits per sample",
+                    String::from_utf8_lossy(&bytes[18..22]),
+                    bits_per_sample
+                ),
+            })?
+        } else {
+            Compression::None
+        };
+        Ok(CommonChunk {
+            nchannels: u16::from_be_bytes([bytes[0], bytes[1]]),
+            nframes: u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+            bits_per_sample,
+            sample_rate: from_extended(&sample_rate),
+            compression,
+        })
+    }
+
+    fn to_bytes(&self, aifc: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.nchannels.to_be_bytes());
+        bytes.extend_from_slice(&self.nframes.to_be_bytes());
+        bytes.extend_from_slice(&self.bits_per_sample.to_be_bytes());
+        bytes.extend_from_slice(&to_extended(self.sample_rate));
+        if aifc {
+            bytes.extend_from_slice(self.compression.fourcc());
+            bytes.extend(pstring(self.compression.name()));
+        }
+        bytes
+    }
+}
+
+/// A Pascal string padded to an even length, as in AIFF-C's COMM chunk.
+fn pstring(s: &str) -> Vec<u8> {
+    let mut bytes = vec![s.len() as u8];
+    bytes.extend_from_slice(s.as_bytes());
+    if bytes.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+/// AIFF stores 8 bit samples signed, WAV unsigned.
+fn flip_sign(samples: Samples) -> Samples {
+    match samples {
+        Samples::U8(x) => Samples::U8(x.into_iter().map(|s| s ^ 0x80).collect()),
+        samples => samples,
+    }
+}
+
+pub fn from_file(filename: &str) -> Result<WAV, AiffError> {
+    let f = File::open(filename)?;
+    from_reader(BufReader::new(f))
+}
+
+/// Reads an AIFF or AIFF-C file into the same in-memory form as a WAV file.
+/// The NAME, AUTH, ANNO, `(c) ` and ID3 chunks become its `metadata`; other
+/// chunks, such as markers, are not carried over.
+pub fn from_reader<R: Read>(mut reader: R) -> Result<WAV, AiffError> {
+    let mut header = Vec::new();
+    reader.by_ref().take(12).read_to_end(&mut header)?;
+    if header.len() < 12 {
+        return Err(AiffError::Truncated {
+            offset: 0,
+            expected: 12,
+            found: header.len() as u64,
+        });
+    }
+    if &header[0..4] != b"FORM" {
+        return Err(AiffError::BadMagic {
+            offset: 0,
+            expected: "FORM",
+            found: String::from_utf8_lossy(&header[0..4]).to_string(),
+        });
+    }
+    let aifc = match &header[8..12] {
+        b"AIFF" => false,
+        b"AIFC" => true,
+        found => {
+            return Err(AiffError::BadMagic {
+                offset: 8,
+                expected: "AIFF",
+                found: String::from_utf8_lossy(found).to_string(),
+            })
+        }
+    };
+
+    let mut offset = 12;
+    let mut comm = None;
+    let mut ssnd = None;
+    let mut metadata = TrackMetadata::default();
+    loop {
+        let mut chunk_header = Vec::new();
+        reader.by_ref().take(8).read_to_end(&mut chunk_header)?;
+        match chunk_header.len() {
+            0 => break,
+            8 => (),
+            found => {
+                return Err(AiffError::Truncated {
+                    offset,
+                    expected: 8,
+                    found: found as u64,
+                })
+            }
+        }
+        let size = u32::from_be_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as u64;
+        let mut data = Vec::new();
+        reader.by_ref().take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(AiffError::Truncated {
+                offset,
+                expected: 8 + size,
+                found: 8 + data.len() as u64,
+            });
+        }
+        if size % 2 == 1 {
+            // a missing pad byte at the very end of the file is harmless
+            reader.by_ref().take(1).read_to_end(&mut Vec::new())?;
+        }
+        match &chunk_header[0..4] {
+            b"COMM" => comm = Some((offset, CommonChunk::new(&data, aifc, offset)?)),
+            b"SSND" => ssnd = Some((offset, data)),
+            // ID3 wins over the AIFF text chunks, as it does over INFO
+            b"NAME" => {
+                metadata.title.get_or_insert(zstr(&data));
+            }
+            b"AUTH" => {
+                metadata.artist.get_or_insert(zstr(&data));
+            }
+            b"ANNO" => {
+                metadata.comment.get_or_insert(zstr(&data));
+            }
+            b"(c) " => metadata.info.push(("ICOP".to_string(), zstr(&data))),
+            b"ID3 " | b"id3 " => {
+                if let Some(tag) = id3::Tag::parse(&data) {
+                    metadata.read_id3(tag);
+                }
+            }
+            _ => (),
+        }
+        offset += 8 + size + size % 2;
+    }
+
+    let (comm_offset, comm) = comm.ok_or(AiffError::MissingChunk { id: "COMM" })?;
+    let (ssnd_offset, ssnd) = ssnd.ok_or(AiffError::MissingChunk { id: "SSND" })?;
+    let format = comm
+        .compression
+        .sample_format(comm.bits_per_sample)
+        .ok_or_else(|| AiffError::Unsupported {
+            offset: comm_offset,
+            reason: format!(
+                "compression {:?} with {} bits per sample",
+                String::from_utf8_lossy(comm.compression.fourcc()),
+                comm.bits_per_sample
+            ),
+        })?;
+    if comm.nchannels == 0 || !(comm.sample_rate >= 1.0 && comm.sample_rate <= u32::MAX as f64) {
+        return Err(AiffError::Corrupt {
+            offset: comm_offset,
+            reason: format!("{} channels at {} Hz", comm.nchannels, comm.sample_rate),
+        });
+    }
+    if ssnd.len() < 8 {
+        return Err(AiffError::Corrupt {
+            offset: ssnd_offset,
+            reason: "SSND chunk is missing its offset and block size".to_string(),
+        });
+    }
+    let data_offset = u32::from_be_bytes([ssnd[0], ssnd[1], ssnd[2], ssnd[3]]) as usize;
+    let data = ssnd.get(8 + data_offset..).unwrap_or(&[]);
+    // trust the frame count over the chunk size, but not past the data there is
+    let frame_size = format.bytes_per_sample() * comm.nchannels as usize;
+    let data = &data[..data.len().min(comm.nframes as usize * frame_size)];
+    let data = &data[..data.len() - data.len() % frame_size];
+
+    let signal = match comm.compression {
+        Compression::Sowt => Samples::from_bytes::<LittleEndian>(format, data),
+        _ => Samples::from_bytes::<BigEndian>(format, data),
+    };
+    let mut wav = WAV::new(
+        comm.sample_rate.round() as u32,
+        comm.nchannels,
+        flip_sign(signal),
+    )
+    .map_err(|e| AiffError::Corrupt {
+        offset: comm_offset,
+        reason: e.to_string(),
+    })?;
+    wav.metadata = metadata;
+    Ok(wav)
+}
+
+pub fn write(wav: &WAV, filename: &str, compression: Compression) -> Result<(), AiffError> {
+    let f = File::create(filename)?;
+    let mut writer = BufWriter::new(f);
+    write_to(wav, &mut writer, compression)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `wav` as AIFF, or as AIFF-C for any `compression` but `None`.
+/// `compression` must suit the signal: `Fl32` and `Fl64` need `F32` and `F64`
+/// samples, the others integer samples.
+pub fn write_to<W: Write>(
+    wav: &WAV,
+    writer: &mut W,
+    compression: Compression,
+) -> Result<(), AiffError> {
+    let format = wav.signal.format();
+    let fits = match compression {
+        Compression::None | Compression::Sowt => !format.is_float(),
+        Compression::Fl32 => format == SampleFormat::F32,
+        Compression::Fl64 => format == SampleFormat::F64,
+    };
+    if !fits {
+        return Err(AiffError::InvalidInput(format!(
+            "{:?} samples cannot be written with {:?} compression",
+            format, compression
+        )));
+    }
+    let nframes = u32::try_from(wav.nframes()).map_err(|_| {
+        AiffError::InvalidInput("AIFF files hold at most 2^32 - 1 frames".to_string())
+    })?;
+    let aifc = compression != Compression::None;
+
+    let comm = CommonChunk {
+        nchannels: wav.nchannels() as u16,
+        nframes,
+        bits_per_sample: format.bits_per_sample(),
+        sample_rate: wav.fmt_header.sample_rate as f64,
+        compression,
+    };
+    let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = Vec::new();
+    if aifc {
+        // the only AIFF-C version there is
+        chunks.push((b"FVER", 0xA280_5140u32.to_be_bytes().to_vec()));
+    }
+    chunks.push((b"COMM", comm.to_bytes(aifc)));
+    let metadata = &wav.metadata;
+    let texts = [
+        (b"NAME", &metadata.title),
+        (b"AUTH", &metadata.artist),
+        (b"ANNO", &metadata.comment),
+    ];
+    for &(id, text) in texts.iter() {
+        if let Some(text) = text {
+            chunks.push((id, text.as_bytes().to_vec()));
+        }
+    }
+    for (id, value) in &metadata.info {
+        if id == "ICOP" {
+            chunks.push((b"(c) ", value.as_bytes().to_vec()));
+        }
+    }
+    if let Some(tag) = metadata.id3_tag() {
+        chunks.push((b"ID3 ", tag.to_bytes()));
+    }
+
+    let data_size = wav.signal.len() * format.bytes_per_sample();
+    let padded = |size: usize| 8 + size + size % 2;
+    let form_size = 4
+        + chunks
+            .iter()
+            .map(|(_, data)| padded(data.len()))
+            .sum::<usize>()
+        + padded(8 + data_size);
+    let form_size = u32::try_from(form_size).map_err(|_| {
+        AiffError::InvalidInput("AIFF files must be smaller than 4 GiB".to_string())
+    })?;
+
+    writer.write_all(b"FORM")?;
+    writer.write_u32::<BigEndian>(form_size)?;
+    writer.write_all(if aifc { b"AIFC" } else { b"AIFF" })?;
+    for (id, data) in &chunks {
+        writer.write_all(*id)?;
+        writer.write_u32::<BigEndian>(data.len() as u32)?;
+        writer.write_all(data)?;
+        if data.len() % 2 == 1 {
+            writer.write_u8(0)?;
+        }
+    }
+    writer.write_all(b"SSND")?;
+    writer.write_u32::<BigEndian>((8 + data_size) as u32)?;
+    // no offset, no block alignment
+    writer.write_all(&[0; 8])?;
+    let signal = flip_sign(wav.signal.clone());
+    match compression {
+        Compression::Sowt => signal.write::<LittleEndian, _>(writer)?,
+        _ => signal.write::<BigEndian, _>(writer)?,
+    }
+    if data_size % 2 == 1 {
+        writer.write_u8(0)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{from_extended, from_reader, to_extended, write_to, zstr, AiffError, Compression};
+    use crate::io::sample::{SampleFormat, Samples};
+    use crate::io::wav::WAV;
+    use std::io::Cursor;
+
+    #[test]
+    fn extended_sample_rates() {
+        let rate = [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0];
+        assert_eq!(from_extended(&rate), 44100.0);
+        for &x in &[0.0, 8000.0, 44100.0, 48000.0, 96000.0, 22254.545454545456] {
+            assert_eq!(from_extended(&to_extended(x)), x);
+        }
+        assert_eq!(to_extended(44100.0), rate);
+    }
+
+    #[test]
+    fn every_compression() {
+        let signal = [0.5, -0.5, 0.25, -0.25, 0.0, 0.75];
+        let cases = [
+            (SampleFormat::U8, Compression::None),
+            (SampleFormat::I16, Compression::None),
+            (SampleFormat::I24, Compression::Sowt),
+            (SampleFormat::I32, Compression::None),
+            (SampleFormat::F32, Compression::Fl32),
+            (SampleFormat::F64, Compression::Fl64),
+        ];
+        for &(format, compression) in &cases {
+            let mut wav = WAV::new(48000, 2, Samples::from_f64(format, &signal)).unwrap();
+            wav.metadata.title = Some("Selected Ambient Works".to_string());
+            wav.metadata.bpm = Some(96.0);
+            let mut bytes = Vec::new();
+            write_to(&wav, &mut bytes, compression).unwrap();
+            assert_eq!(
+                &bytes[8..12],
+                if compression == Compression::None {
+                    b"AIFF"
+                } else {
+                    b"AIFC"
+                }
+            );
+
+            let aiff = from_reader(Cursor::new(bytes)).unwrap();
+            assert_eq!(aiff.signal, wav.signal);
+            assert_eq!(aiff.fmt_header.sample_rate, 48000);
+            assert_eq!(aiff.nchannels(), 2);
+            assert_eq!(aiff.metadata.title, wav.metadata.title);
+            assert_eq!(aiff.metadata.bpm, Some(96.0));
+        }
+    }
+
+    #[test]
+    fn signed_8_bit_samples() {
+        let wav = WAV::new(8000, 1, Samples::U8(vec![0, 128, 255])).unwrap();
+        let mut bytes = Vec::new();
+        write_to(&wav, &mut bytes, Compression::None).unwrap();
+        let ssnd = bytes.len() - 4;
+        assert_eq!(&bytes[ssnd..ssnd + 3], &[0x80, 0x00, 0x7f]);
+    }
+
+    #[test]
+    fn rejects_mismatched_compression() {
+        let wav = WAV::new(8000, 1, Samples::F32(vec![0.0])).unwrap();
+        match write_to(&wav, &mut Vec::new(), Compression::Sowt) {
+            Err(AiffError::InvalidInput(_)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_ascii_text() {
+        let mut wav = WAV::new(8000, 1, Samples::I16(vec![0])).unwrap();
+        wav.metadata.title = Some("Café".to_string());
+        let mut bytes = Vec::new();
+        write_to(&wav, &mut bytes, Compression::None).unwrap();
+        let name = bytes.windows(4).position(|id| id == b"NAME").unwrap();
+        assert_eq!(&bytes[name + 8..name + 13], "Café".as_bytes());
+
+        let aiff = from_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(aiff.metadata.title.as_deref(), Some("Café"));
+        assert_eq!(zstr(b"Caf\xc3\xa9\0"), "Café");
+        assert_eq!(zstr(b"Caf\xe9"), "Café");
+    }
+}
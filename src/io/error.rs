@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong reading or writing an audio file, whatever
+/// its container. Variants that come from the file's contents carry the byte
+/// offset of the structure at fault so a broken file can be inspected with a
+/// hex editor.
+#[derive(Debug)]
+pub enum ContainerError {
+    /// the file ended after `found` bytes of an `expected` byte structure
+    /// starting at `offset`
+    Truncated {
+        offset: u64,
+        expected: u64,
+        found: u64,
+    },
+    /// the bytes at `offset` are not the id that belongs there
+    BadMagic {
+        offset: u64,
+        expected: &'static str,
+        found: String,
+    },
+    /// the structure at `offset` describes an encoding or feature cldj
+    /// cannot handle
+    Unsupported {
+        offset: u64,
+        reason: String,
+    },
+    /// the structure at `offset` breaks the format: a header field
+    /// contradicts another, a CRC fails or an event cannot be decoded
+    Corrupt {
+        offset: u64,
+        reason: String,
+    },
+    /// a chunk every file of the format needs is absent
+    MissingChunk {
+        id: &'static str,
+    },
+    /// the decoded audio does not match the MD5 signature the file carries
+    Md5Mismatch {
+        expected: [u8; 16],
+        found: [u8; 16],
+    },
+    /// the file starts with bytes no decoder recognizes
+    UnknownFormat {
+        found: Vec<u8>,
+    },
+    /// the caller asked to write something the file cannot hold
+    InvalidInput(String),
+    Io(io::Error),
+}
+
+impl ContainerError {
+    /// The same error with its offset moved `by` bytes later, for errors
+    /// found parsing a slice that starts partway into the file.
+    pub(crate) fn shifted(self, by: u64) -> ContainerError {
+        match self {
+            ContainerError::Truncated {
+                offset,
+                expected,
+                found,
+            } => ContainerError::Truncated {
+                offset: offset + by,
+                expected,
+                found,
+            },
+            ContainerError::BadMagic {
+                offset,
+                expected,
+                found,
+            } => ContainerError::BadMagic {
+                offset: offset + by,
+                expected,
+                found,
+            },
+            ContainerError::Unsupported { offset, reason } => ContainerError::Unsupported {
+                offset: offset + by,
+                reason,
+            },
+            ContainerError::Corrupt { offset, reason } => ContainerError::Corrupt {
+                offset: offset + by,
+                reason,
+            },
+            e => e,
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerError::Truncated {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "truncated at byte {}: expected {} bytes, found {}",
+                offset, expected, found
+            ),
+            ContainerError::BadMagic {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "bad magic at byte {}: expected {:?}, found {:?}",
+                offset, expected, found
+            ),
+            ContainerError::Unsupported { offset, reason } => {
+                write!(f, "unsupported at byte {}: {}", offset, reason)
+            }
+            ContainerError::Corrupt { offset, reason } => {
+                write!(f, "corrupt at byte {}: {}", offset, reason)
+            }
+            ContainerError::MissingChunk { id } => write!(f, "no {:?} chunk found", id),
+            ContainerError::Md5Mismatch { expected, found } => write!(
+                f,
+                "MD5 mismatch: the file has {}, the audio hashes to {}",
+                hex(expected),
+                hex(found)
+            ),
+            ContainerError::UnknownFormat { found } => write!(
+                f,
+                "unknown format: the file starts with {:?}",
+                String::from_utf8_lossy(found)
+            ),
+            ContainerError::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            ContainerError::Io(e) => write!(f, "i/o error: {}", e),
+        }
+    }
+}
+
+impl Error for ContainerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ContainerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ContainerError {
+    fn from(e: io::Error) -> ContainerError {
+        ContainerError::Io(e)
+    }
+}
@@ -0,0 +1,271 @@
+use super::FlacError;
+
+/// Reads big endian bit fields, most significant bit first, out of a byte
+/// slice.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// position in bits from the start of `data`
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0 }
+    }
+
+    /// The offset of the byte holding the next bit.
+    pub fn byte_pos(&self) -> usize {
+        self.pos / 8
+    }
+
+    /// Moves to the start of byte `byte`.
+    pub fn seek(&mut self, byte: usize) {
+        self.pos = byte * 8;
+    }
+
+    /// Everything being read, including what has been read already.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The error for running out of data with `bits` still to read.
+    fn truncated(&self, bits: u32) -> FlacError {
+        FlacError::Truncated {
+            offset: self.byte_pos() as u64,
+            expected: (bits as u64).div_ceil(8),
+            found: 0,
+        }
+    }
+
+    /// Reads an `n` bit unsigned integer, `n` at most 64.
+    pub fn read_bits(&mut self, n: u32) -> Result<u64, FlacError> {
+        let mut value = 0u64;
+        let mut left = n;
+        while left > 0 {
+            let byte = *self
+                .data
+                .get(self.pos / 8)
+                .ok_or_else(|| self.truncated(left))? as u64;
+            let available = 8 - (self.pos % 8) as u32;
+            let take = available.min(left);
+            let bits = (byte >> (available - take)) & ((1 << take) - 1);
+            value = (value << take) | bits;
+            left -= take;
+            self.pos += take as usize;
+        }
+        Ok(value)
+    }
+
+    /// Reads an `n` bit two's complement integer.
+    pub fn read_signed(&mut self, n: u32) -> Result<i64, FlacError> {
+        if n == 0 {
+            return Ok(0);
+        }
+        let value = self.read_bits(n)?;
+        let shift = 64 - n;
+        Ok(((value << shift) as i64) >> shift)
+    }
+
+    /// Counts zero bits up to and including the next one bit.
+    pub fn read_unary(&mut self) -> Result<u64, FlacError> {
+        let mut count = 0;
+        loop {
+            let byte = *self
+                .data
+                .get(self.pos / 8)
+                .ok_or_else(|| self.truncated(1))?;
+            let bit = (self.pos % 8) as u32;
+            let rest = byte << bit;
+            if rest == 0 {
+                count += (8 - bit) as u64;
+                self.pos += (8 - bit) as usize;
+            } else {
+                let zeros = rest.leading_zeros();
+                count += zeros as u64;
+                self.pos += zeros as usize + 1;
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Reads a Rice coded signed integer with parameter `k`.
+    pub fn read_rice(&mut self, k: u32) -> Result<i64, FlacError> {
+        let quotient = self.read_unary()?;
+        let value = (quotient << k) | self.read_bits(k)?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    /// Skips to the next byte boundary.
+    pub fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+}
+
+/// Packs big endian bit fields into bytes, most significant bit first.
+#[derive(Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    /// bits not yet making up a whole byte, in the low `nbits` bits
+    acc: u64,
+    nbits: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter::default()
+    }
+
+    /// The number of bits written so far.
+    pub fn len(&self) -> usize {
+        self.bytes.len() * 8 + self.nbits as usize
+    }
+
+    /// Writes the low `n` bits of `value`, `n` at most 64.
+    pub fn write_bits(&mut self, value: u64, n: u32) {
+        if n > 32 {
+            self.write_bits(value >> 32, n - 32);
+            self.write_bits(value, 32);
+            return;
+        }
+        let mask = (1u64 << n) - 1;
+        self.acc = (self.acc << n) | (value & mask);
+        self.nbits += n;
+        while self.nbits >= 8 {
+            self.nbits -= 8;
+            self.bytes.push((self.acc >> self.nbits) as u8);
+        }
+    }
+
+    /// Writes `value` as an `n` bit two's complement integer.
+    pub fn write_signed(&mut self, value: i64, n: u32) {
+        self.write_bits(value as u64, n);
+    }
+
+    /// Writes `n` zero bits followed by a one bit.
+    pub fn write_unary(&mut self, mut n: u64) {
+        while n >= 32 {
+            self.write_bits(0, 32);
+            n -= 32;
+        }
+        self.write_bits(1, n as u32 + 1);
+    }
+
+    /// Writes a Rice coded signed integer with parameter `k`.
+    pub fn write_rice(&mut self, value: i64, k: u32) {
+        let folded = ((value << 1) ^ (value >> 63)) as u64;
+        self.write_unary(folded >> k);
+        self.write_bits(folded, k);
+    }
+
+    /// Appends everything written to `other`.
+    pub fn append(&mut self, other: &BitWriter) {
+        for &byte in &other.bytes {
+            self.write_bits(byte as u64, 8);
+        }
+        self.write_bits(other.acc, other.nbits);
+    }
+
+    /// Pads with zero bits to the next byte boundary.
+    pub fn align(&mut self) {
+        if self.nbits > 0 {
+            self.write_bits(0, 8 - self.nbits);
+        }
+    }
+
+    /// The bytes written so far, which must end on a byte boundary.
+    pub fn bytes(&self) -> &[u8] {
+        debug_assert_eq!(self.nbits, 0);
+        &self.bytes
+    }
+}
+
+const fn crc8_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = (n as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+const CRC8: [u8; 256] = crc8_table();
+const CRC16: [u16; 256] = crc16_table();
+
+/// The CRC-8 closing a frame header, polynomial x^8 + x^2 + x + 1.
+pub fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &b| CRC8[(crc ^ b) as usize])
+}
+
+/// The CRC-16 closing a frame, polynomial x^16 + x^15 + x^2 + 1.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &b| {
+        (crc << 8) ^ CRC16[((crc >> 8) as u8 ^ b) as usize]
+    })
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{crc16, crc8, BitReader, BitWriter};
+
+    #[test]
+    fn fields() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(0x3ffe, 14);
+        writer.write_signed(-3, 5);
+        writer.write_unary(40);
+        writer.write_bits(0xf_ffff_ffff, 36);
+        for &(value, k) in &[(0, 0), (-1, 0), (1000, 4), (-77777, 12)] {
+            writer.write_rice(value, k);
+        }
+        writer.align();
+
+        let mut reader = BitReader::new(writer.bytes());
+        assert_eq!(reader.read_bits(14).unwrap(), 0x3ffe);
+        assert_eq!(reader.read_signed(5).unwrap(), -3);
+        assert_eq!(reader.read_unary().unwrap(), 40);
+        assert_eq!(reader.read_bits(36).unwrap(), 0xf_ffff_ffff);
+        for &(value, k) in &[(0, 0), (-1, 0), (1000, 4), (-77777, 12)] {
+            assert_eq!(reader.read_rice(k).unwrap(), value);
+        }
+        reader.align();
+        assert_eq!(reader.byte_pos(), writer.bytes().len());
+        assert!(reader.read_bits(1).is_err());
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xfee8);
+    }
+}
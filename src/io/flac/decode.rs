@@ -0,0 +1,436 @@
+use super::bits::{crc16, crc8, BitReader};
+use super::{FlacError, StreamInfo};
+
+/// The coefficients of the fixed predictors of order 0 to 4, applied to the
+/// previous samples newest first.
+pub(super) const FIXED: [&[i64]; 5] = [&[], &[1], &[2, -1], &[3, -3, 1], &[4, -6, 4, -1]];
+
+/// The fewest bytes a frame can take: a six byte header, a mono constant
+/// subframe of two bytes and the CRC-16.
+const MIN_FRAME_SIZE: usize = 10;
+
+/// How the channels of a frame were decorrelated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Stereo {
+    /// every channel coded on its own
+    Independent(u32),
+    LeftSide,
+    SideRight,
+    MidSide,
+}
+
+impl Stereo {
+    pub(super) fn code(self) -> u64 {
+        match self {
+            Stereo::Independent(n) => n as u64 - 1,
+            Stereo::LeftSide => 8,
+            Stereo::SideRight => 9,
+            Stereo::MidSide => 10,
+        }
+    }
+
+    fn nchannels(self) -> u32 {
+        match self {
+            Stereo::Independent(n) => n,
+            _ => 2,
+        }
+    }
+
+    /// Whether channel `n` is a side channel, which needs an extra bit.
+    fn is_side(self, n: usize) -> bool {
+        match self {
+            Stereo::LeftSide | Stereo::MidSide => n == 1,
+            Stereo::SideRight => n == 0,
+            Stereo::Independent(_) => false,
+        }
+    }
+}
+
+fn corrupt(offset: usize, reason: &str) -> FlacError {
+    FlacError::Corrupt {
+        offset: offset as u64,
+        reason: reason.to_string(),
+    }
+}
+
+/// Reads the UTF-8 style coded frame or sample number.
+fn read_coded_number(reader: &mut BitReader) -> Result<u64, FlacError> {
+    let offset = reader.byte_pos();
+    let lead = reader.read_bits(8)?;
+    let ones = (lead as u8).leading_ones();
+    match ones {
+        0 => return Ok(lead),
+        1 | 8 => return Err(corrupt(offset, "bad frame number")),
+        _ => (),
+    }
+    let mut value = lead & (0x7f >> ones);
+    for _ in 1..ones {
+        let byte = reader.read_bits(8)?;
+        if byte & 0xc0 != 0x80 {
+            return Err(corrupt(offset, "bad frame number"));
+        }
+        value = (value << 6) | (byte & 0x3f);
+    }
+    Ok(value)
+}
+
+/// The fields of a frame header that decoding the frame needs.
+struct FrameHeader {
+    block_size: usize,
+    stereo: Stereo,
+    bits_per_sample: u32,
+}
+
+fn read_frame_header(reader: &mut BitReader, info: &StreamInfo) -> Result<FrameHeader, FlacError> {
+    let start = reader.byte_pos();
+    if reader.read_bits(15)? != 0x7ffc {
+        return Err(corrupt(start, "no frame sync code"));
+    }
+    // the blocking strategy only changes what the coded number counts
+    reader.read_bits(1)?;
+    let block_size_code = reader.read_bits(4)?;
+    let sample_rate_code = reader.read_bits(4)?;
+    let stereo = match reader.read_bits(4)? {
+        n @ 0..=7 => Stereo::Independent(n as u32 + 1),
+        8 => Stereo::LeftSide,
+        9 => Stereo::SideRight,
+        10 => Stereo::MidSide,
+        _ => return Err(corrupt(start, "reserved channel assignment")),
+    };
+    let bits_per_sample = match reader.read_bits(3)? {
+        0 => info.bits_per_sample as u32,
+        1 => 8,
+        2 => 12,
+        4 => 16,
+        5 => 20,
+        6 => 24,
+        7 => 32,
+        _ => return Err(corrupt(start, "reserved sample size")),
+    };
+    if reader.read_bits(1)? != 0 {
+        return Err(corrupt(start, "reserved bit set"));
+    }
+    read_coded_number(reader)?;
+    let block_size = match block_size_code {
+        0 => return Err(corrupt(start, "reserved block size")),
+        1 => 192,
+        n @ 2..=5 => 576 << (n - 2),
+        6 => reader.read_bits(8)? as usize + 1,
+        7 => reader.read_bits(16)? as usize + 1,
+        n => 256 << (n - 8),
+    };
+    // the sample rate comes from STREAMINFO, but its bits must be skipped
+    match sample_rate_code {
+        12 => {
+            reader.read_bits(8)?;
+        }
+        13 | 14 => {
+            reader.read_bits(16)?;
+        }
+        15 => return Err(corrupt(start, "invalid sample rate")),
+        _ => (),
+    }
+    let end = reader.byte_pos();
+    let crc = reader.read_bits(8)? as u8;
+    if crc8(&reader.data()[start..end]) != crc {
+        return Err(corrupt(start, "frame header CRC mismatch"));
+    }
+
+    if stereo.nchannels() != info.nchannels as u32 {
+        return Err(corrupt(
+            start,
+            &format!(
+                "frame has {} channels, STREAMINFO {}",
+                stereo.nchannels(),
+                info.nchannels
+            ),
+        ));
+    }
+    if bits_per_sample != info.bits_per_sample as u32 {
+        return Err(corrupt(
+            start,
+            &format!(
+                "frame has {} bits per sample, STREAMINFO {}",
+                bits_per_sample, info.bits_per_sample
+            ),
+        ));
+    }
+    Ok(FrameHeader {
+        block_size,
+        stereo,
+        bits_per_sample,
+    })
+}
+
+/// Reads the residual of a predictor of order `order` into `samples`, which
+/// already holds the warm up samples.
+fn read_residual(
+    reader: &mut BitReader,
+    block_size: usize,
+    order: usize,
+    samples: &mut Vec<i64>,
+) -> Result<(), FlacError> {
+    let offset = reader.byte_pos();
+    let param_bits = match reader.read_bits(2)? {
+        0 => 4,
+        1 => 5,
+        _ => return Err(corrupt(offset, "reserved residual coding method")),
+    };
+    let escape = (1 << param_bits) - 1;
+    let partition_order = reader.read_bits(4)?;
+    let partitions = 1 << partition_order;
+    if !block_size.is_multiple_of(partitions) || block_size / partitions < order {
+        return Err(corrupt(offset, "partition order too high for block"));
+    }
+    for partition in 0..partitions {
+        let mut count = block_size / partitions;
+        if partition == 0 {
+            count -= order;
+        }
+        let param = reader.read_bits(param_bits)?;
+        if param == escape {
+            let bits = reader.read_bits(5)? as u32;
+            for _ in 0..count {
+                samples.push(reader.read_signed(bits)?);
+            }
+        } else {
+            for _ in 0..count {
+                samples.push(reader.read_rice(param as u32)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the residual following the warm up samples with the signal it
+/// encodes, given predictor coefficients newest sample first. Returns `None`
+/// if the arithmetic overflows or a sample does not fit in `bits` bits,
+/// which only a corrupt stream can cause.
+fn predict(samples: &mut [i64], coefficients: &[i64], shift: u32, bits: u32) -> Option<()> {
+    let order = coefficients.len();
+    let limit = 1i64 << (bits - 1);
+    for n in order..samples.len() {
+        let mut prediction = 0i64;
+        for (c, s) in coefficients.iter().zip(samples[n - order..n].iter().rev()) {
+            prediction = prediction.checked_add(c.checked_mul(*s)?)?;
+        }
+        let sample = samples[n].checked_add(prediction >> shift)?;
+        if sample < -limit || sample >= limit {
+            return None;
+        }
+        samples[n] = sample;
+    }
+    Some(())
+}
+
+fn read_subframe(
+    reader: &mut BitReader,
+    block_size: usize,
+    bits_per_sample: u32,
+) -> Result<Vec<i64>, FlacError> {
+    let offset = reader.byte_pos();
+    if reader.read_bits(1)? != 0 {
+        return Err(corrupt(offset, "subframe padding bit set"));
+    }
+    let kind = reader.read_bits(6)?;
+    let wasted = if reader.read_bits(1)? == 1 {
+        reader.read_unary()? as u32 + 1
+    } else {
+        0
+    };
+    if wasted >= bits_per_sample {
+        return Err(corrupt(offset, "more wasted bits than bits per sample"));
+    }
+    let bits = bits_per_sample - wasted;
+
+    let mut samples = Vec::with_capacity(block_size);
+    match kind {
+        0 => samples.resize(block_size, reader.read_signed(bits)?),
+        1 => {
+            for _ in 0..block_size {
+                samples.push(reader.read_signed(bits)?);
+            }
+        }
+        8..=12 => {
+            let order = kind as usize - 8;
+            if order > block_size {
+                return Err(corrupt(offset, "predictor order exceeds block size"));
+            }
+            for _ in 0..order {
+                samples.push(reader.read_signed(bits)?);
+            }
+            read_residual(reader, block_size, order, &mut samples)?;
+            predict(&mut samples, FIXED[order], 0, bits)
+                .ok_or_else(|| corrupt(offset, "prediction out of range"))?;
+        }
+        32..=63 => {
+            let order = kind as usize - 31;
+            if order > block_size {
+                return Err(corrupt(offset, "predictor order exceeds block size"));
+            }
+            for _ in 0..order {
+                samples.push(reader.read_signed(bits)?);
+            }
+            let precision = reader.read_bits(4)? as u32 + 1;
+            if precision == 16 {
+                return Err(corrupt(offset, "invalid coefficient precision"));
+            }
+            let shift = reader.read_signed(5)?;
+            if shift < 0 {
+                return Err(corrupt(offset, "negative prediction shift"));
+            }
+            let coefficients = (0..order)
+                .map(|_| reader.read_signed(precision))
+                .collect::<Result<Vec<_>, _>>()?;
+            read_residual(reader, block_size, order, &mut samples)?;
+            predict(&mut samples, &coefficients, shift as u32, bits)
+                .ok_or_else(|| corrupt(offset, "prediction out of range"))?;
+        }
+        _ => return Err(corrupt(offset, "reserved subframe type")),
+    }
+    if wasted > 0 {
+        for s in samples.iter_mut() {
+            *s <<= wasted;
+        }
+    }
+    Ok(samples)
+}
+
+/// Decodes the frame starting at the reader's position, appending its
+/// samples to `channels`.
+fn read_frame(
+    reader: &mut BitReader,
+    info: &StreamInfo,
+    channels: &mut [Vec<i64>],
+) -> Result<(), FlacError> {
+    let start = reader.byte_pos();
+    let header = read_frame_header(reader, info)?;
+    let mut subframes = Vec::with_capacity(channels.len());
+    for n in 0..channels.len() {
+        let side = header.stereo.is_side(n) as u32;
+        subframes.push(read_subframe(
+            reader,
+            header.block_size,
+            header.bits_per_sample + side,
+        )?);
+    }
+    reader.align();
+    let end = reader.byte_pos();
+    let crc = reader.read_bits(16)? as u16;
+    if crc16(&reader.data()[start..end]) != crc {
+        return Err(corrupt(start, "frame CRC mismatch"));
+    }
+
+    if header.stereo != Stereo::Independent(channels.len() as u32) {
+        let (a, b) = subframes.split_at_mut(1);
+        for (a, b) in a[0].iter_mut().zip(b[0].iter_mut()) {
+            let (left, right) = match header.stereo {
+                Stereo::LeftSide => (*a, *a - *b),
+                Stereo::SideRight => (*a + *b, *b),
+                _ => {
+                    let mid = (*a << 1) | (*b & 1);
+                    ((mid + *b) >> 1, (mid - *b) >> 1)
+                }
+            };
+            *a = left;
+            *b = right;
+        }
+    }
+    for (channel, subframe) in channels.iter_mut().zip(subframes) {
+        channel.extend(subframe);
+    }
+    Ok(())
+}
+
+/// Decodes every frame from byte `start` of `bytes` into one `Vec` per
+/// channel. Decoding stops at the first byte that cannot start a frame, so
+/// a trailing ID3v1 tag is ignored, but not before the sample count
+/// STREAMINFO promises.
+pub(super) fn frames(
+    bytes: &[u8],
+    start: usize,
+    info: &StreamInfo,
+) -> Result<Vec<Vec<i64>>, FlacError> {
+    let mut channels = vec![Vec::new(); info.nchannels as usize];
+    // trust the sample count only as far as the bytes left could hold it,
+    // at one block in every smallest possible frame
+    let nframes = bytes.len().saturating_sub(start) / MIN_FRAME_SIZE;
+    let capacity = info
+        .total_samples
+        .min(nframes as u64 * info.max_block_size as u64);
+    for channel in channels.iter_mut() {
+        channel.reserve(capacity as usize);
+    }
+    let mut reader = BitReader::new(bytes);
+    reader.seek(start);
+    loop {
+        let pos = reader.byte_pos();
+        let sync = bytes
+            .get(pos..pos + 2)
+            .is_some_and(|b| b[0] == 0xff && b[1] & 0xfe == 0xf8);
+        if !sync {
+            break;
+        }
+        read_frame(&mut reader, info, &mut channels)?;
+    }
+    let decoded = channels[0].len() as u64;
+    if decoded < info.total_samples {
+        let pos = reader.byte_pos();
+        return Err(if pos < bytes.len() {
+            corrupt(pos, "no frame sync code")
+        } else {
+            // the stream ended where the next frame's sync code belongs
+            FlacError::Truncated {
+                offset: pos as u64,
+                expected: 2,
+                found: 0,
+            }
+        });
+    }
+    Ok(channels)
+}
+
+#[cfg(test)]
+mod lpc {
+    use super::{predict, read_subframe};
+    use crate::io::flac::bits::{BitReader, BitWriter};
+
+    #[test]
+    fn lpc_subframe_with_escaped_partition() {
+        let mut writer = BitWriter::new();
+        // LPC of order 2, no wasted bits
+        writer.write_bits(32 + 1, 7);
+        writer.write_bits(0, 1);
+        writer.write_signed(10, 16);
+        writer.write_signed(12, 16);
+        // 4 bit coefficients 4 and -2, shifted right by 1
+        writer.write_bits(3, 4);
+        writer.write_signed(1, 5);
+        writer.write_signed(4, 4);
+        writer.write_signed(-2, 4);
+        // one partition, escaped to 4 bit verbatim residuals
+        writer.write_bits(0, 2);
+        writer.write_bits(0, 4);
+        writer.write_bits(15, 4);
+        writer.write_bits(4, 5);
+        for &r in &[1, -1, -3] {
+            writer.write_signed(r, 4);
+        }
+        writer.align();
+
+        let mut reader = BitReader::new(writer.bytes());
+        let samples = read_subframe(&mut reader, 5, 16).unwrap();
+        assert_eq!(samples, vec![10, 12, 15, 17, 16]);
+    }
+
+    #[test]
+    fn overflowing_prediction() {
+        let mut samples = vec![100, 5];
+        assert_eq!(predict(&mut samples, &[2], 1, 16), Some(()));
+        assert_eq!(samples, vec![100, 105]);
+
+        assert_eq!(predict(&mut [30000, 5000], &[1], 0, 16), None);
+        assert_eq!(predict(&mut [i64::MAX, 0], &[2], 0, 33), None);
+        assert_eq!(predict(&mut [1, i64::MAX], &[1], 0, 33), None);
+    }
+}
@@ -0,0 +1,267 @@
+use super::bits::{crc16, crc8, BitWriter};
+use super::decode::{Stereo, FIXED};
+
+/// Frames per block. libFLAC uses the same for its default compression
+/// levels.
+pub(super) const BLOCK_SIZE: usize = 4096;
+
+/// The highest partition order tried when coding a residual.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+fn sample_size_code(bits_per_sample: u32) -> u64 {
+    match bits_per_sample {
+        8 => 1,
+        12 => 2,
+        16 => 4,
+        20 => 5,
+        24 => 6,
+        32 => 7,
+        // read from STREAMINFO
+        _ => 0,
+    }
+}
+
+/// Writes `value` in the UTF-8 style coding frame headers use, which
+/// stretches to 36 bits.
+fn write_coded_number(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write_bits(value, 8);
+        return;
+    }
+    let mut len = 2;
+    while value >= 1 << (5 * len + 1) {
+        len += 1;
+    }
+    let lead = (0xff00 >> len) as u64 & 0xff;
+    writer.write_bits(lead | (value >> (6 * (len - 1))), 8);
+    for n in (0..len - 1).rev() {
+        writer.write_bits(0x80 | ((value >> (6 * n)) & 0x3f), 8);
+    }
+}
+
+fn fold(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// How to Rice code a residual.
+struct Partitions {
+    order: u32,
+    /// the Rice parameter of each partition
+    params: Vec<u32>,
+    /// an upper bound on the size of the coded residual
+    bits: u64,
+}
+
+/// Picks the partition order and Rice parameters that code `residual`, the
+/// output of a predictor of order `order` over `block_size` samples, in the
+/// fewest bits.
+fn partition(residual: &[i64], order: usize, block_size: usize) -> Partitions {
+    let mut best: Option<Partitions> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let count = 1 << partition_order;
+        if !block_size.is_multiple_of(count) || block_size / count < order {
+            break;
+        }
+        let mut start = 0;
+        let mut params = Vec::with_capacity(count);
+        let mut bits = 0;
+        for n in 0..count {
+            let len = block_size / count - if n == 0 { order } else { 0 };
+            let part = &residual[start..start + len];
+            start += len;
+            let sum: u64 = part.iter().map(|&r| fold(r)).sum();
+            // sum >> k bounds the unary bits from above
+            let (param, cost) = (0..=30)
+                .map(|k| (k, len as u64 * (k as u64 + 1) + (sum >> k)))
+                .min_by_key(|&(_, cost)| cost)
+                .unwrap();
+            params.push(param);
+            bits += cost;
+        }
+        let param_bits = if params.iter().any(|&k| k > 14) { 5 } else { 4 };
+        bits += 6 + count as u64 * param_bits;
+        if best.as_ref().is_none_or(|best| bits < best.bits) {
+            best = Some(Partitions {
+                order: partition_order,
+                params,
+                bits,
+            });
+        }
+    }
+    best.unwrap()
+}
+
+fn write_residual(writer: &mut BitWriter, residual: &[i64], order: usize, partitions: &Partitions) {
+    let wide = partitions.params.iter().any(|&k| k > 14);
+    writer.write_bits(wide as u64, 2);
+    writer.write_bits(partitions.order as u64, 4);
+    let mut start = 0;
+    let partition_size = (residual.len() + order) >> partitions.order;
+    for (n, &param) in partitions.params.iter().enumerate() {
+        let len = partition_size - if n == 0 { order } else { 0 };
+        writer.write_bits(param as u64, if wide { 5 } else { 4 });
+        for &r in &residual[start..start + len] {
+            writer.write_rice(r, param);
+        }
+        start += len;
+    }
+}
+
+/// Codes one channel of a block of `bits_per_sample` bit samples as the
+/// smallest of a constant, fixed predictor or verbatim subframe.
+fn subframe(samples: &[i64], bits_per_sample: u32) -> BitWriter {
+    let mut writer = BitWriter::new();
+    if samples.iter().all(|&s| s == samples[0]) {
+        writer.write_bits(0, 8);
+        writer.write_signed(samples[0], bits_per_sample);
+        return writer;
+    }
+
+    // low bits that are zero in every sample need not be coded
+    let wasted = samples
+        .iter()
+        .fold(0, |acc, &s| acc | s)
+        .trailing_zeros()
+        .min(bits_per_sample - 1);
+    let shifted: Vec<i64>;
+    let samples = if wasted > 0 {
+        shifted = samples.iter().map(|&s| s >> wasted).collect();
+        &shifted
+    } else {
+        samples
+    };
+    let bits = bits_per_sample - wasted;
+
+    let mut best = None;
+    let mut best_bits = samples.len() as u64 * bits as u64;
+    for (order, coefficients) in FIXED.iter().enumerate() {
+        if order > samples.len() {
+            break;
+        }
+        let residual: Vec<i64> = (order..samples.len())
+            .map(|n| {
+                let prediction: i64 = coefficients
+                    .iter()
+                    .zip(samples[n - order..n].iter().rev())
+                    .map(|(c, s)| c * s)
+                    .sum();
+                samples[n] - prediction
+            })
+            .collect();
+        // decoders hold residuals in 32 bits
+        if residual
+            .iter()
+            .any(|&r| r < i32::MIN as i64 || r > i32::MAX as i64)
+        {
+            continue;
+        }
+        let partitions = partition(&residual, order, samples.len());
+        let bits = order as u64 * bits as u64 + partitions.bits;
+        if bits < best_bits {
+            best_bits = bits;
+            best = Some((order, residual, partitions));
+        }
+    }
+
+    let kind = match best {
+        Some((order, _, _)) => 8 + order as u64,
+        None => 1,
+    };
+    writer.write_bits(kind, 7);
+    if wasted > 0 {
+        writer.write_bits(1, 1);
+        writer.write_unary(wasted as u64 - 1);
+    } else {
+        writer.write_bits(0, 1);
+    }
+    match best {
+        Some((order, residual, partitions)) => {
+            for &s in &samples[..order] {
+                writer.write_signed(s, bits);
+            }
+            write_residual(&mut writer, &residual, order, &partitions);
+        }
+        None => {
+            for &s in samples {
+                writer.write_signed(s, bits);
+            }
+        }
+    }
+    writer
+}
+
+/// Codes one block of samples, one slice per channel, as frame `number`.
+pub(super) fn frame(number: u64, channels: &[&[i64]], bits_per_sample: u32) -> Vec<u8> {
+    let (stereo, subframes) = if channels.len() == 2 {
+        let (left, right) = (channels[0], channels[1]);
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let left = subframe(left, bits_per_sample);
+        let right = subframe(right, bits_per_sample);
+        let side = subframe(&side, bits_per_sample + 1);
+        let mid = subframe(&mid, bits_per_sample);
+        let sizes = [
+            left.len() + right.len(),
+            left.len() + side.len(),
+            side.len() + right.len(),
+            mid.len() + side.len(),
+        ];
+        match (0..sizes.len()).min_by_key(|&n| sizes[n]).unwrap() {
+            0 => (Stereo::Independent(2), vec![left, right]),
+            1 => (Stereo::LeftSide, vec![left, side]),
+            2 => (Stereo::SideRight, vec![side, right]),
+            _ => (Stereo::MidSide, vec![mid, side]),
+        }
+    } else {
+        let subframes = channels
+            .iter()
+            .map(|samples| subframe(samples, bits_per_sample))
+            .collect();
+        (Stereo::Independent(channels.len() as u32), subframes)
+    };
+
+    let mut writer = BitWriter::new();
+    write_header(
+        &mut writer,
+        number,
+        stereo,
+        channels[0].len(),
+        bits_per_sample,
+    );
+    for subframe in &subframes {
+        writer.append(subframe);
+    }
+    writer.align();
+    let crc = crc16(writer.bytes());
+    writer.write_bits(crc as u64, 16);
+    writer.bytes().to_vec()
+}
+
+fn write_header(
+    writer: &mut BitWriter,
+    number: u64,
+    stereo: Stereo,
+    block_size: usize,
+    bits_per_sample: u32,
+) {
+    // sync code, then fixed block size
+    writer.write_bits(0x7ffc, 15);
+    writer.write_bits(0, 1);
+    let (code, extra) = match block_size {
+        BLOCK_SIZE => (12, None),
+        n if n <= 256 => (6, Some(8)),
+        _ => (7, Some(16)),
+    };
+    writer.write_bits(code, 4);
+    // sample rate from STREAMINFO
+    writer.write_bits(0, 4);
+    writer.write_bits(stereo.code(), 4);
+    writer.write_bits(sample_size_code(bits_per_sample), 3);
+    writer.write_bits(0, 1);
+    write_coded_number(writer, number);
+    if let Some(bits) = extra {
+        writer.write_bits(block_size as u64 - 1, bits);
+    }
+    let crc = crc8(writer.bytes());
+    writer.write_bits(crc as u64, 8);
+}
@@ -0,0 +1,533 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use super::error::ContainerError;
+use super::id3;
+use super::sample::Samples;
+use super::wav::{TrackMetadata, WAV};
+
+mod bits;
+mod decode;
+mod encode;
+mod md5;
+
+use bits::{BitReader, BitWriter};
+use md5::Md5;
+
+/// Errors reading or writing FLAC files.
+pub type FlacError = ContainerError;
+
+/// The STREAMINFO block every FLAC file starts with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    /// the smallest frame in bytes; 0 if unknown
+    pub min_frame_size: u32,
+    /// the largest frame in bytes; 0 if unknown
+    pub max_frame_size: u32,
+    pub sample_rate: u32,
+    pub nchannels: u8,
+    pub bits_per_sample: u8,
+    /// frames per channel; 0 if unknown
+    pub total_samples: u64,
+    /// the MD5 of the decoded samples; all zero if unknown
+    pub md5: [u8; 16],
+}
+
+const STREAMINFO_SIZE: usize = 34;
+
+impl StreamInfo {
+    fn new(bytes: &[u8], offset: usize) -> Result<StreamInfo, FlacError> {
+        if bytes.len() < STREAMINFO_SIZE {
+            return Err(FlacError::Corrupt {
+                offset: offset as u64,
+                reason: format!("STREAMINFO is {} bytes, expected 34", bytes.len()),
+            });
+        }
+        let mut reader = BitReader::new(bytes);
+        let mut info = StreamInfo {
+            min_block_size: reader.read_bits(16)? as u16,
+            max_block_size: reader.read_bits(16)? as u16,
+            min_frame_size: reader.read_bits(24)? as u32,
+            max_frame_size: reader.read_bits(24)? as u32,
+            sample_rate: reader.read_bits(20)? as u32,
+            nchannels: reader.read_bits(3)? as u8 + 1,
+            bits_per_sample: reader.read_bits(5)? as u8 + 1,
+            total_samples: reader.read_bits(36)?,
+            md5: [0; 16],
+        };
+        info.md5.copy_from_slice(&bytes[18..STREAMINFO_SIZE]);
+        if info.sample_rate == 0 || info.bits_per_sample < 4 {
+            return Err(FlacError::Corrupt {
+                offset: offset as u64,
+                reason: format!(
+                    "{} bits per sample at {} Hz",
+                    info.bits_per_sample, info.sample_rate
+                ),
+            });
+        }
+        Ok(info)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = BitWriter::new();
+        writer.write_bits(self.min_block_size as u64, 16);
+        writer.write_bits(self.max_block_size as u64, 16);
+        writer.write_bits(self.min_frame_size as u64, 24);
+        writer.write_bits(self.max_frame_size as u64, 24);
+        writer.write_bits(self.sample_rate as u64, 20);
+        writer.write_bits(self.nchannels as u64 - 1, 3);
+        writer.write_bits(self.bits_per_sample as u64 - 1, 5);
+        writer.write_bits(self.total_samples, 36);
+        let mut bytes = writer.bytes().to_vec();
+        bytes.extend_from_slice(&self.md5);
+        bytes
+    }
+}
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+
+/// Vorbis comment names of the `TrackMetadata` fields, as written.
+const COMMENT_FIELDS: [&str; 8] = [
+    "TITLE", "ARTIST", "ALBUM", "GENRE", "DATE", "COMMENT", "BPM", "KEY",
+];
+
+fn read_comment(metadata: &mut TrackMetadata, name: &str, value: String) {
+    match name.to_ascii_uppercase().as_str() {
+        "TITLE" => metadata.title = Some(value),
+        "ARTIST" => metadata.artist = Some(value),
+        "ALBUM" => metadata.album = Some(value),
+        "GENRE" => metadata.genre = Some(value),
+        "DATE" => metadata.date = Some(value),
+        "COMMENT" | "DESCRIPTION" => metadata.comment = Some(value),
+        "BPM" => metadata.bpm = value.trim().parse().ok(),
+        "KEY" | "INITIALKEY" => metadata.key = Some(value),
+        _ => (),
+    }
+}
+
+/// The `len` bytes at `*pos`, moving `pos` past them.
+fn take<'a>(bytes: &'a [u8], pos: &mut usize, len: usize) -> Option<&'a [u8]> {
+    let field = bytes.get(*pos..pos.checked_add(len)?)?;
+    *pos += len;
+    Some(field)
+}
+
+fn take_u32_le(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+    let b = take(bytes, pos, 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// Parses a VORBIS_COMMENT block, which unlike the rest of FLAC is little
+/// endian. Returns `None` if the lengths run past the block.
+fn read_comments(metadata: &mut TrackMetadata, bytes: &[u8]) -> Option<()> {
+    let mut pos = 0;
+    let vendor = take_u32_le(bytes, &mut pos)?;
+    take(bytes, &mut pos, vendor)?;
+    for _ in 0..take_u32_le(bytes, &mut pos)? {
+        let len = take_u32_le(bytes, &mut pos)?;
+        let comment = String::from_utf8_lossy(take(bytes, &mut pos, len)?);
+        if let Some(split) = comment.find('=') {
+            read_comment(
+                metadata,
+                &comment[..split],
+                comment[split + 1..].to_string(),
+            );
+        }
+    }
+    Some(())
+}
+
+fn comments(metadata: &TrackMetadata) -> Vec<u8> {
+    let bpm = metadata.bpm.map(|bpm| bpm.to_string());
+    let values = [
+        &metadata.title,
+        &metadata.artist,
+        &metadata.album,
+        &metadata.genre,
+        &metadata.date,
+        &metadata.comment,
+        &bpm,
+        &metadata.key,
+    ];
+    let comments: Vec<String> = COMMENT_FIELDS
+        .iter()
+        .zip(values.iter())
+        .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+        .collect();
+
+    let vendor = "cldj";
+    let mut bytes = (vendor.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(vendor.as_bytes());
+    bytes.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        bytes.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(comment.as_bytes());
+    }
+    bytes
+}
+
+/// The MD5 FLAC signs its audio with: every sample, interleaved, as a
+/// little endian signed integer of the fewest whole bytes that hold it.
+fn signature(channels: &[Vec<i64>], bits_per_sample: u32) -> [u8; 16] {
+    let width = (bits_per_sample as usize).div_ceil(8);
+    let mut md5 = Md5::new();
+    let mut frame = Vec::with_capacity(width * channels.len());
+    for n in 0..channels.first().map_or(0, Vec::len) {
+        frame.clear();
+        for channel in channels {
+            frame.extend_from_slice(&channel[n].to_le_bytes()[..width]);
+        }
+        md5.update(&frame);
+    }
+    md5.finish()
+}
+
+pub fn from_file(filename: &str) -> Result<WAV, FlacError> {
+    let f = File::open(filename)?;
+    from_reader(BufReader::new(f))
+}
+
+/// Decodes a FLAC file into the same in-memory form as a WAV file, checking
+/// every CRC and, if STREAMINFO has one, the MD5 signature. Samples of an
+/// odd width, such as 12 or 20 bits, are widened into the next sample format
+/// up. Vorbis comments and any ID3v2 tag in front of the stream become its
+/// `metadata`; other metadata blocks, such as pictures, are not carried over.
+pub fn from_reader<R: Read>(mut reader: R) -> Result<WAV, FlacError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut metadata = TrackMetadata::default();
+    let mut pos = 0;
+    // some taggers put ID3v2 in front of the stream
+    if bytes.starts_with(b"ID3") && bytes.len() >= 10 {
+        // an ID3v2.4 footer repeats the header after the tag
+        let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+        let end = (10 + id3::syncsafe(&bytes[6..10]) + footer).min(bytes.len());
+        if let Some(tag) = id3::Tag::parse(&bytes[..end]) {
+            metadata.read_id3(tag);
+        }
+        pos = end;
+    }
+    match bytes.get(pos..pos + 4) {
+        Some(b"fLaC") => (),
+        found => {
+            return Err(FlacError::BadMagic {
+                offset: pos as u64,
+                expected: "fLaC",
+                found: String::from_utf8_lossy(found.unwrap_or(&bytes[pos..])).to_string(),
+            })
+        }
+    }
+    pos += 4;
+    let info_offset = pos as u64;
+
+    let mut info = None;
+    loop {
+        let truncated = |expected: usize| FlacError::Truncated {
+            offset: pos as u64,
+            expected: expected as u64,
+            found: (bytes.len() - pos) as u64,
+        };
+        let header = bytes.get(pos..pos + 4).ok_or_else(|| truncated(4))?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let block = bytes
+            .get(pos + 4..pos + 4 + size)
+            .ok_or_else(|| truncated(4 + size))?;
+        match kind {
+            STREAMINFO => info = Some(StreamInfo::new(block, pos)?),
+            VORBIS_COMMENT => {
+                let mut comments = TrackMetadata::default();
+                if read_comments(&mut comments, block).is_some() {
+                    metadata = merge(comments, metadata);
+                }
+            }
+            _ => (),
+        }
+        if info.is_none() {
+            return Err(FlacError::Corrupt {
+                offset: pos as u64,
+                reason: "the first metadata block is not STREAMINFO".to_string(),
+            });
+        }
+        pos += 4 + size;
+        if last {
+            break;
+        }
+    }
+    let info = info.unwrap();
+
+    let channels = decode::frames(&bytes, pos, &info)?;
+    let bits = info.bits_per_sample as u32;
+    if info.md5 != [0; 16] {
+        let found = signature(&channels, bits);
+        if found != info.md5 {
+            return Err(FlacError::Md5Mismatch {
+                expected: info.md5,
+                found,
+            });
+        }
+    }
+
+    let nframes = channels[0].len();
+    let interleaved = (0..nframes).flat_map(|n| channels.iter().map(move |c| c[n]));
+    let signal = match bits {
+        1..=8 => Samples::U8(
+            interleaved
+                .map(|s| ((s << (8 - bits)) + 128) as u8)
+                .collect(),
+        ),
+        9..=16 => Samples::I16(interleaved.map(|s| (s << (16 - bits)) as i16).collect()),
+        17..=24 => Samples::I24(interleaved.map(|s| (s << (24 - bits)) as i32).collect()),
+        _ => Samples::I32(interleaved.map(|s| (s << (32 - bits)) as i32).collect()),
+    };
+    let mut wav = WAV::new(info.sample_rate, info.nchannels as u16, signal).map_err(|e| {
+        FlacError::Corrupt {
+            offset: info_offset,
+            reason: e.to_string(),
+        }
+    })?;
+    wav.metadata = metadata;
+    Ok(wav)
+}
+
+/// `comments` with any field it lacks taken from `id3`, so Vorbis comments
+/// win over a stray ID3 tag.
+fn merge(mut comments: TrackMetadata, id3: TrackMetadata) -> TrackMetadata {
+    comments.title = comments.title.or(id3.title);
+    comments.artist = comments.artist.or(id3.artist);
+    comments.album = comments.album.or(id3.album);
+    comments.genre = comments.genre.or(id3.genre);
+    comments.date = comments.date.or(id3.date);
+    comments.comment = comments.comment.or(id3.comment);
+    comments.bpm = comments.bpm.or(id3.bpm);
+    comments.key = comments.key.or(id3.key);
+    comments
+}
+
+pub fn write(wav: &WAV, filename: &str) -> Result<(), FlacError> {
+    let f = File::create(filename)?;
+    let mut writer = BufWriter::new(f);
+    write_to(wav, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encodes `wav` as FLAC with fixed predictors, a Vorbis comment block
+/// holding its `metadata` and an MD5 signature. FLAC holds integer samples
+/// only, so float signals are refused.
+pub fn write_to<W: Write>(wav: &WAV, writer: &mut W) -> Result<(), FlacError> {
+    let format = wav.signal.format();
+    if format.is_float() {
+        return Err(FlacError::InvalidInput(format!(
+            "FLAC cannot hold {:?} samples",
+            format
+        )));
+    }
+    let nchannels = wav.nchannels();
+    if !(1..=8).contains(&nchannels) {
+        return Err(FlacError::InvalidInput(format!(
+            "FLAC holds 1 to 8 channels, not {}",
+            nchannels
+        )));
+    }
+    let sample_rate = wav.fmt_header.sample_rate;
+    if sample_rate == 0 || sample_rate >= 1 << 20 {
+        return Err(FlacError::InvalidInput(format!(
+            "FLAC cannot hold a sample rate of {} Hz",
+            sample_rate
+        )));
+    }
+    let nframes = wav.nframes();
+    if nframes as u64 >= 1 << 36 {
+        return Err(FlacError::InvalidInput(
+            "FLAC files hold fewer than 2^36 frames".to_string(),
+        ));
+    }
+
+    let bits = format.bits_per_sample() as u32;
+    let mut channels = vec![Vec::with_capacity(nframes); nchannels];
+    for n in 0..wav.signal.len() {
+        let sample = match &wav.signal {
+            Samples::U8(x) => x[n] as i64 - 128,
+            Samples::I16(x) => x[n] as i64,
+            Samples::I24(x) | Samples::I32(x) => x[n] as i64,
+            Samples::F32(_) | Samples::F64(_) => unreachable!(),
+        };
+        channels[n % nchannels].push(sample);
+    }
+
+    let mut frames = Vec::new();
+    let mut min_frame_size = u32::MAX;
+    let mut max_frame_size = 0;
+    for (number, start) in (0..nframes).step_by(encode::BLOCK_SIZE).enumerate() {
+        let end = (start + encode::BLOCK_SIZE).min(nframes);
+        let block: Vec<&[i64]> = channels.iter().map(|c| &c[start..end]).collect();
+        let frame = encode::frame(number as u64, &block, bits);
+        min_frame_size = min_frame_size.min(frame.len() as u32);
+        max_frame_size = max_frame_size.max(frame.len() as u32);
+        frames.extend(frame);
+    }
+
+    let info = StreamInfo {
+        min_block_size: encode::BLOCK_SIZE as u16,
+        max_block_size: encode::BLOCK_SIZE as u16,
+        min_frame_size: if frames.is_empty() { 0 } else { min_frame_size },
+        max_frame_size,
+        sample_rate,
+        nchannels: nchannels as u8,
+        bits_per_sample: bits as u8,
+        total_samples: nframes as u64,
+        md5: signature(&channels, bits),
+    };
+    let blocks = [
+        (STREAMINFO, info.to_bytes()),
+        (VORBIS_COMMENT, comments(&wav.metadata)),
+    ];
+    writer.write_all(b"fLaC")?;
+    for (n, (kind, block)) in blocks.iter().enumerate() {
+        let last = if n + 1 == blocks.len() { 0x80 } else { 0 };
+        writer.write_all(&[last | kind])?;
+        writer.write_all(&(block.len() as u32).to_be_bytes()[1..])?;
+        writer.write_all(block)?;
+    }
+    writer.write_all(&frames)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{from_reader, write_to, FlacError};
+    use crate::io::sample::{SampleFormat, Samples};
+    use crate::io::wav::WAV;
+    use std::io::Cursor;
+
+    /// A few seconds of something more like music than silence: a chord
+    /// with a decaying envelope, some clipping and a run of silence.
+    fn signal(nchannels: usize, nframes: usize) -> Vec<f64> {
+        let mut signal = Vec::with_capacity(nchannels * nframes);
+        for n in 0..nframes {
+            let t = n as f64 / 44100.0;
+            let envelope = (-3.0 * t).exp();
+            for c in 0..nchannels {
+                let s = if n > nframes / 2 && n < nframes / 2 + 5000 {
+                    0.0
+                } else {
+                    envelope
+                        * (0.5 * (2.0 * std::f64::consts::PI * 220.0 * t + c as f64).sin()
+                            + 0.4 * (2.0 * std::f64::consts::PI * 277.2 * t).sin()
+                            + 0.3 * (2.0 * std::f64::consts::PI * 329.6 * t).sin())
+                };
+                signal.push(s.clamp(-0.9, 0.9));
+            }
+        }
+        signal
+    }
+
+    #[test]
+    fn every_format() {
+        for &format in &[
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::I24,
+            SampleFormat::I32,
+        ] {
+            for &(nchannels, nframes) in &[(1, 10000), (2, 9000), (3, 100), (2, 1)] {
+                let samples = Samples::from_f64(format, &signal(nchannels, nframes));
+                let mut wav = WAV::new(44100, nchannels as u16, samples).unwrap();
+                wav.metadata.title = Some("Windowlicker".to_string());
+                wav.metadata.bpm = Some(127.5);
+                let mut bytes = Vec::new();
+                write_to(&wav, &mut bytes).unwrap();
+                if format == SampleFormat::I16 && nframes > 1000 {
+                    assert!(bytes.len() < wav.signal.len() * 2 * 3 / 4);
+                }
+
+                let flac = from_reader(Cursor::new(&bytes)).unwrap();
+                assert_eq!(flac.signal, wav.signal);
+                assert_eq!(flac.fmt_header.sample_rate, 44100);
+                assert_eq!(flac.nchannels(), nchannels);
+                assert_eq!(flac.metadata.title, wav.metadata.title);
+                assert_eq!(flac.metadata.bpm, Some(127.5));
+            }
+        }
+    }
+
+    #[test]
+    fn corruption_is_caught() {
+        let samples = Samples::from_f64(SampleFormat::I16, &signal(2, 5000));
+        let mut bytes = Vec::new();
+        write_to(&WAV::new(48000, 2, samples).unwrap(), &mut bytes).unwrap();
+
+        // the last byte belongs to the final frame's CRC
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 0x10;
+        assert!(matches!(
+            from_reader(Cursor::new(&flipped)),
+            Err(FlacError::Corrupt { .. })
+        ));
+
+        // a bad signature with intact frames
+        let mut signed = bytes.clone();
+        // fLaC, block header, then 18 bytes before the MD5
+        signed[8 + 18] ^= 1;
+        assert!(matches!(
+            from_reader(Cursor::new(&signed)),
+            Err(FlacError::Md5Mismatch { .. })
+        ));
+
+        let cut = &bytes[..bytes.len() / 2];
+        assert!(matches!(
+            from_reader(Cursor::new(cut)),
+            Err(FlacError::Truncated { .. }) | Err(FlacError::Corrupt { .. })
+        ));
+
+        // a sample count far past what the frames hold is not allocated
+        let mut inflated = bytes.clone();
+        inflated[8 + 13] |= 0x0f;
+        for b in &mut inflated[8 + 14..8 + 18] {
+            *b = 0xff;
+        }
+        assert!(matches!(
+            from_reader(Cursor::new(&inflated)),
+            Err(FlacError::Truncated { .. })
+        ));
+    }
+
+    /// A stereo file of LPC subframes, one frame each of mid/side,
+    /// left/side and independent channels, the last one short. It was
+    /// written by a separate encoder following the specification rather
+    /// than by `write_to`, so the two cannot share a misreading.
+    #[test]
+    fn lpc_fixture() {
+        const MD5: &str = "5db58422de1034df0f9f2c4f13e3a288";
+        let bytes = std::fs::read("data/lpc_44100Hz_16bit_stereo.flac").unwrap();
+        let hex: String = bytes[8 + 18..8 + 34]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(hex, MD5);
+
+        let flac = from_reader(Cursor::new(&bytes)).unwrap();
+        assert_eq!(flac.fmt_header.sample_rate, 44100);
+        assert_eq!(flac.nchannels(), 2);
+        assert_eq!(flac.nframes(), 2 * 4096 + 1000);
+        match &flac.signal {
+            Samples::I16(samples) => assert_eq!(&samples[10000..10002], &[-7713, -1370]),
+            other => panic!("decoded as {:?}", other.format()),
+        }
+    }
+
+    #[test]
+    fn floats_are_refused() {
+        let wav = WAV::new(44100, 1, Samples::F32(vec![0.0; 16])).unwrap();
+        assert!(matches!(
+            write_to(&wav, &mut Vec::new()),
+            Err(FlacError::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+//! ID3v2 tags, as embedded in the `id3 ` chunk of WAV files and the `ID3 `
+//! chunk of AIFF files. Only versions 2.3 and 2.4 are understood; frames are
+//! kept as raw bytes and decoded on request.
+
+/// One frame of a tag, e.g. `TIT2` holding the title.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub id: String,
+    pub flags: u16,
+    pub data: Vec<u8>,
+}
+
+/// An ID3v2 tag: the major version, 3 or 4, and its frames in file order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub version: u8,
+    pub frames: Vec<Frame>,
+}
+
+const LATIN1: u8 = 0;
+const UTF16: u8 = 1;
+const UTF16BE: u8 = 2;
+const UTF8: u8 = 3;
+
+/// The size in a tag or frame header: big endian, seven bits to a byte.
+pub(crate) fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |size, &b| (size << 7) | (b & 0x7f) as usize)
+}
+
+fn to_syncsafe(size: usize) -> [u8; 4] {
+    [
+        (size >> 21) as u8 & 0x7f,
+        (size >> 14) as u8 & 0x7f,
+        (size >> 7) as u8 & 0x7f,
+        size as u8 & 0x7f,
+    ]
+}
+
+impl Tag {
+    pub fn new(version: u8) -> Tag {
+        Tag {
+            version,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Parses a tag starting with the `ID3` magic. Returns `None` for anything
+    /// cldj cannot read, including ID3v2.2 and unsynchronised tags, so the
+    /// caller can keep the bytes verbatim instead.
+    pub fn parse(bytes: &[u8]) -> Option<Tag> {
+        if bytes.len() < 10 || &bytes[0..3] != b"ID3" {
+            return None;
+        }
+        let version = bytes[3];
+        let flags = bytes[5];
+        if !(version == 3 || version == 4) || flags & 0x80 != 0 {
+            return None;
+        }
+        let end = (10 + syncsafe(&bytes[6..10])).min(bytes.len());
+        let mut pos = 10;
+        if flags & 0x40 != 0 {
+            // skip the extended header
+            let size = &bytes.get(10..14)?;
+            pos += match version {
+                3 => 4 + u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize,
+                _ => syncsafe(size),
+            };
+        }
+
+        let mut frames = Vec::new();
+        // whatever follows the last frame is zero padding
+        while pos + 10 <= end && bytes[pos] != 0 {
+            let header = &bytes[pos..pos + 10];
+            let size = match version {
+                3 => u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize,
+                _ => syncsafe(&header[4..8]),
+            };
+            let data = bytes.get(pos + 10..pos + 10 + size)?;
+            frames.push(Frame {
+                id: String::from_utf8_lossy(&header[0..4]).to_string(),
+                flags: u16::from_be_bytes([header[8], header[9]]),
+                data: data.to_vec(),
+            });
+            pos += 10 + size;
+        }
+        Some(Tag { version, frames })
+    }
+
+    /// The tag as written to a file, without padding.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for frame in &self.frames {
+            body.extend_from_slice(frame.id.as_bytes());
+            match self.version {
+                3 => body.extend_from_slice(&(frame.data.len() as u32).to_be_bytes()),
+                _ => body.extend_from_slice(&to_syncsafe(frame.data.len())),
+            }
+            body.extend_from_slice(&frame.flags.to_be_bytes());
+            body.extend_from_slice(&frame.data);
+        }
+        let mut bytes = vec![b'I', b'D', b'3', self.version, 0, 0];
+        bytes.extend_from_slice(&to_syncsafe(body.len()));
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn frame(&self, id: &str) -> Option<&Frame> {
+        self.frames.iter().find(|frame| frame.id == id)
+    }
+
+    /// The first value of text frame `id`, such as `TIT2` or `TBPM`.
+    pub fn text(&self, id: &str) -> Option<String> {
+        let data = &self.frame(id)?.data;
+        let (&encoding, text) = data.split_first()?;
+        let text = decode(encoding, text);
+        // ID3v2.4 separates multiple values with nulls
+        text.split('\0').next().map(|value| value.to_string())
+    }
+
+    /// Replaces text frame `id` with `text`, or removes it when `None`.
+    pub fn set_text(&mut self, id: &str, text: Option<&str>) {
+        let data = text.map(|text| {
+            let encoding = self.encoding_for(text);
+            let mut data = vec![encoding];
+            data.extend(encode(encoding, text));
+            data
+        });
+        self.replace(|frame| frame.id == id, id, data);
+    }
+
+    /// The text of the `COMM` frame with an empty description, which players
+    /// show as the comment.
+    pub fn comment(&self) -> Option<String> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.id == "COMM")
+            .filter_map(|frame| split_comment(&frame.data))
+            .find(|(description, _)| description.is_empty())
+            .map(|(_, text)| text)
+    }
+
+    /// Replaces the comment `comment` returns, or removes it when `None`.
+    pub fn set_comment(&mut self, comment: Option<&str>) {
+        let data = comment.map(|comment| {
+            let encoding = self.encoding_for(comment);
+            let mut data = vec![encoding];
+            data.extend_from_slice(b"eng");
+            // an empty description
+            data.extend(encode(encoding, ""));
+            data.extend_from_slice(terminator(encoding));
+            data.extend(encode(encoding, comment));
+            data
+        });
+        let is_comment = |frame: &Frame| {
+            frame.id == "COMM"
+                && split_comment(&frame.data).is_some_and(|(description, _)| description.is_empty())
+        };
+        self.replace(is_comment, "COMM", data);
+    }
+
+    /// Removes the first frame matching `matches` and puts a frame holding
+    /// `data` in its place, or at the end if there was none.
+    fn replace<F: Fn(&Frame) -> bool>(&mut self, matches: F, id: &str, data: Option<Vec<u8>>) {
+        let position = self.frames.iter().position(matches);
+        match (position, data) {
+            (Some(n), Some(data)) => self.frames[n].data = data,
+            (Some(n), None) => {
+                self.frames.remove(n);
+            }
+            (None, Some(data)) => self.frames.push(Frame {
+                id: id.to_string(),
+                flags: 0,
+                data,
+            }),
+            (None, None) => (),
+        }
+    }
+
+    /// ID3v2.4 text is always written as UTF-8. ID3v2.3 has no UTF-8, so
+    /// text outside Latin-1 is written as UTF-16.
+    fn encoding_for(&self, text: &str) -> u8 {
+        if self.version >= 4 {
+            UTF8
+        } else if text.chars().all(|c| (c as u32) < 0x100) {
+            LATIN1
+        } else {
+            UTF16
+        }
+    }
+}
+
+fn terminator(encoding: u8) -> &'static [u8] {
+    match encoding {
+        UTF16 | UTF16BE => &[0, 0],
+        _ => &[0],
+    }
+}
+
+/// Splits a `COMM` frame into its description and text.
+fn split_comment(data: &[u8]) -> Option<(String, String)> {
+    let (&encoding, rest) = data.split_first()?;
+    // skip the language code
+    let rest = rest.get(3..)?;
+    let terminator = terminator(encoding);
+    let width = terminator.len();
+    let end = (0..rest.len() / width)
+        .map(|n| n * width)
+        .find(|&n| &rest[n..n + width] == terminator)?;
+    let description = decode(encoding, &rest[..end]);
+    let text = decode(encoding, &rest[end + width..]);
+    Some((description, text.trim_end_matches('\0').to_string()))
+}
+
+fn decode(encoding: u8, bytes: &[u8]) -> String {
+    match encoding {
+        LATIN1 => bytes.iter().map(|&b| b as char).collect(),
+        UTF16 | UTF16BE => {
+            let (big_endian, bytes) = match bytes {
+                [0xff, 0xfe, rest @ ..] => (false, rest),
+                [0xfe, 0xff, rest @ ..] => (true, rest),
+                _ => (encoding == UTF16BE, bytes),
+            };
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|unit| {
+                    if big_endian {
+                        u16::from_be_bytes([unit[0], unit[1]])
+                    } else {
+                        u16::from_le_bytes([unit[0], unit[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+fn encode(encoding: u8, text: &str) -> Vec<u8> {
+    match encoding {
+        LATIN1 => text.chars().map(|c| c as u8).collect(),
+        UTF16 => {
+            let mut bytes = vec![0xff, 0xfe];
+            bytes.extend(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+            bytes
+        }
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{Frame, Tag};
+
+    #[test]
+    fn text_frames() {
+        for &version in &[3, 4] {
+            let mut tag = Tag::new(version);
+            tag.set_text("TIT2", Some("Windowlicker"));
+            tag.set_text("TPE1", Some("Aphex Twin"));
+            tag.set_text("TKEY", Some("F♯m"));
+            tag.set_comment(Some("peak time"));
+            let tag = Tag::parse(&tag.to_bytes()).unwrap();
+            assert_eq!(tag.text("TIT2").unwrap(), "Windowlicker");
+            assert_eq!(tag.text("TPE1").unwrap(), "Aphex Twin");
+            assert_eq!(tag.text("TKEY").unwrap(), "F♯m");
+            assert_eq!(tag.comment().unwrap(), "peak time");
+            assert_eq!(tag.text("TBPM"), None);
+        }
+    }
+
+    #[test]
+    fn set_text_replaces_and_removes() {
+        let mut tag = Tag::new(3);
+        tag.set_text("TBPM", Some("120"));
+        tag.set_text("TBPM", Some("128"));
+        assert_eq!(tag.frames.len(), 1);
+        assert_eq!(tag.text("TBPM").unwrap(), "128");
+        tag.set_text("TBPM", None);
+        assert!(tag.frames.is_empty());
+    }
+
+    #[test]
+    fn unknown_frames_and_padding() {
+        let mut tag = Tag::new(4);
+        tag.frames.push(Frame {
+            id: "PRIV".to_string(),
+            flags: 0,
+            data: vec![1, 2, 3],
+        });
+        let mut bytes = tag.to_bytes();
+        // declare and append 8 bytes of padding
+        bytes[9] += 8;
+        bytes.extend_from_slice(&[0; 8]);
+        assert_eq!(Tag::parse(&bytes).unwrap(), tag);
+    }
+
+    #[test]
+    fn unsupported_versions() {
+        let mut bytes = Tag::new(3).to_bytes();
+        bytes[3] = 2;
+        assert_eq!(Tag::parse(&bytes), None);
+        assert_eq!(Tag::parse(b"TAG"), None);
+    }
+}
@@ -0,0 +1,860 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use super::error::ContainerError;
+
+/// Errors reading or writing Standard MIDI Files.
+pub type MidiError = ContainerError;
+
+/// How the tracks of a file relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// format 0: one track holding every channel
+    SingleTrack,
+    /// format 1: tracks played together, the first holding the tempo map
+    Parallel,
+}
+
+/// What a tick is worth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// ticks per quarter note, so the length of a tick follows the tempo
+    Metrical(u16),
+    /// a fixed number of ticks per SMPTE frame; `fps` is 24, 25, 29 for
+    /// 29.97 drop frame, or 30
+    Timecode { fps: u8, ticks_per_frame: u8 },
+}
+
+/// A meta event, which only matters to sequencers and never reaches a synth.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Meta {
+    /// microseconds per quarter note
+    Tempo(u32),
+    TimeSignature {
+        numerator: u8,
+        /// the note value of a beat; a power of two
+        denominator: u8,
+        /// MIDI clocks per metronome click
+        clocks_per_click: u8,
+        /// 32nd notes per 24 MIDI clocks
+        thirty_seconds_per_quarter: u8,
+    },
+    KeySignature {
+        /// positive for sharps, negative for flats
+        sharps: i8,
+        minor: bool,
+    },
+    /// text events 0x01 to 0x0f, such as track names (0x03), markers (0x05)
+    /// and cue points (0x07)
+    Text {
+        kind: u8,
+        text: String,
+    },
+    EndOfTrack,
+    /// any other meta event, kept verbatim
+    Other {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Meta {
+    pub const TRACK_NAME: u8 = 0x03;
+    pub const MARKER: u8 = 0x05;
+    pub const CUE_POINT: u8 = 0x07;
+}
+
+/// A MIDI event. Channels are zero indexed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    NoteOff {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    /// a note on with velocity 0 is a note off
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        key: u8,
+        pressure: u8,
+    },
+    Controller {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// a 14 bit bend, 0x2000 being no bend
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    /// a system exclusive message, without the leading 0xf0
+    SysEx(Vec<u8>),
+    /// bytes sent as they are, such as the rest of a split SysEx
+    Escape(Vec<u8>),
+    Meta(Meta),
+}
+
+impl EventKind {
+    /// The status nibble, channel and data bytes of a channel message.
+    fn channel_message(&self) -> Option<(u8, u8, Vec<u8>)> {
+        let message = match *self {
+            EventKind::NoteOff {
+                channel,
+                key,
+                velocity,
+            } => (0x80, channel, vec![key, velocity]),
+            EventKind::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => (0x90, channel, vec![key, velocity]),
+            EventKind::PolyPressure {
+                channel,
+                key,
+                pressure,
+            } => (0xa0, channel, vec![key, pressure]),
+            EventKind::Controller {
+                channel,
+                controller,
+                value,
+            } => (0xb0, channel, vec![controller, value]),
+            EventKind::ProgramChange { channel, program } => (0xc0, channel, vec![program]),
+            EventKind::ChannelPressure { channel, pressure } => (0xd0, channel, vec![pressure]),
+            EventKind::PitchBend { channel, value } => (
+                0xe0,
+                channel,
+                vec![(value & 0x7f) as u8, ((value >> 7) & 0x7f) as u8],
+            ),
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+/// An event at an absolute position in its track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// ticks since the start of the track
+    pub tick: u64,
+    pub kind: EventKind,
+}
+
+/// An event placed on the timeline of the whole file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEvent {
+    pub seconds: f64,
+    pub tick: u64,
+    /// the index of the track holding the event
+    pub track: usize,
+    pub kind: EventKind,
+}
+
+/// Converts ticks to seconds, following every tempo change in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    timing: Timing,
+    /// (tick, seconds at that tick, microseconds per quarter note from then
+    /// on), in tick order
+    changes: Vec<(u64, f64, u32)>,
+}
+
+/// 120 beats per minute, the tempo until the first tempo event.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+impl TempoMap {
+    pub fn seconds(&self, tick: u64) -> f64 {
+        match self.timing {
+            Timing::Metrical(ppq) => {
+                let n = self.changes.partition_point(|&(t, _, _)| t <= tick);
+                let (start, seconds, tempo) = match n {
+                    0 => (0, 0.0, DEFAULT_TEMPO),
+                    n => self.changes[n - 1],
+                };
+                seconds + (tick - start) as f64 * tempo as f64 / 1e6 / ppq.max(1) as f64
+            }
+            Timing::Timecode {
+                fps,
+                ticks_per_frame,
+            } => {
+                let fps = if fps == 29 {
+                    30_000.0 / 1001.0
+                } else {
+                    fps as f64
+                };
+                tick as f64 / (fps * ticks_per_frame.max(1) as f64)
+            }
+        }
+    }
+
+    /// The tempo in beats per minute at `tick`.
+    pub fn bpm(&self, tick: u64) -> f64 {
+        let n = self.changes.partition_point(|&(t, _, _)| t <= tick);
+        let tempo = match n {
+            0 => DEFAULT_TEMPO,
+            n => self.changes[n - 1].2,
+        };
+        60e6 / tempo as f64
+    }
+}
+
+/// A Standard MIDI File of format 0 or 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smf {
+    pub format: Format,
+    pub timing: Timing,
+    /// tracks holding their events in tick order
+    pub tracks: Vec<Vec<Event>>,
+}
+
+/// Parses the bytes of a file, keeping track of the offset for errors.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MidiError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or(MidiError::Truncated {
+            offset: self.pos as u64,
+            expected: len as u64,
+            found: (self.bytes.len() - self.pos) as u64,
+        })?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads a variable length quantity: seven bits a byte, most significant
+    /// first, at most four bytes.
+    fn vlq(&mut self) -> Result<u32, MidiError> {
+        let offset = self.pos;
+        let mut value = 0;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            value = (value << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiError::Corrupt {
+            offset: offset as u64,
+            reason: "variable length quantity longer than 4 bytes".to_string(),
+        })
+    }
+}
+
+fn write_vlq<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    writer.write_all(&bytes)
+}
+
+/// Text in meta events is ASCII by the standard and UTF-8 or Latin-1 in
+/// practice.
+fn text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+fn read_meta(kind: u8, data: &[u8]) -> Meta {
+    match (kind, data.len()) {
+        (0x51, 3) => Meta::Tempo(u32::from_be_bytes([0, data[0], data[1], data[2]])),
+        (0x58, 4) if data[1] < 8 => Meta::TimeSignature {
+            numerator: data[0],
+            denominator: 1 << data[1],
+            clocks_per_click: data[2],
+            thirty_seconds_per_quarter: data[3],
+        },
+        (0x59, 2) if data[1] <= 1 => Meta::KeySignature {
+            sharps: data[0] as i8,
+            minor: data[1] == 1,
+        },
+        (0x01..=0x0f, _) => Meta::Text {
+            kind,
+            text: text(data),
+        },
+        (0x2f, _) => Meta::EndOfTrack,
+        _ => Meta::Other {
+            kind,
+            data: data.to_vec(),
+        },
+    }
+}
+
+fn meta_bytes(meta: &Meta) -> (u8, Vec<u8>) {
+    match meta {
+        Meta::Tempo(tempo) => (0x51, tempo.to_be_bytes()[1..].to_vec()),
+        Meta::TimeSignature {
+            numerator,
+            denominator,
+            clocks_per_click,
+            thirty_seconds_per_quarter,
+        } => (
+            0x58,
+            vec![
+                *numerator,
+                denominator.trailing_zeros() as u8,
+                *clocks_per_click,
+                *thirty_seconds_per_quarter,
+            ],
+        ),
+        Meta::KeySignature { sharps, minor } => (0x59, vec![*sharps as u8, *minor as u8]),
+        Meta::Text { kind, text } => (*kind, text.as_bytes().to_vec()),
+        Meta::EndOfTrack => (0x2f, Vec::new()),
+        Meta::Other { kind, data } => (*kind, data.clone()),
+    }
+}
+
+/// Reads the events of a track chunk, stopping at its end of track event.
+fn read_track(parser: &mut Parser) -> Result<Vec<Event>, MidiError> {
+    let mut events = Vec::new();
+    let mut tick = 0;
+    let mut running_status = None;
+    while parser.pos < parser.bytes.len() {
+        tick += parser.vlq()? as u64;
+        let offset = parser.pos;
+        let mut status = parser.u8()?;
+        if status < 0x80 {
+            // running status: the byte just read is the first data byte
+            status = running_status.ok_or_else(|| MidiError::Corrupt {
+                offset: offset as u64,
+                reason: "data byte without a status byte".to_string(),
+            })?;
+            parser.pos -= 1;
+        }
+        let kind = match status {
+            0x80..=0xef => {
+                running_status = Some(status);
+                let channel = status & 0x0f;
+                let len = if matches!(status & 0xf0, 0xc0 | 0xd0) {
+                    1
+                } else {
+                    2
+                };
+                let data = parser.take(len)?;
+                if data.iter().any(|&b| b >= 0x80) {
+                    return Err(MidiError::Corrupt {
+                        offset: offset as u64,
+                        reason: "status byte where a data byte belongs".to_string(),
+                    });
+                }
+                match status & 0xf0 {
+                    0x80 => EventKind::NoteOff {
+                        channel,
+                        key: data[0],
+                        velocity: data[1],
+                    },
+                    0x90 => EventKind::NoteOn {
+                        channel,
+                        key: data[0],
+                        velocity: data[1],
+                    },
+                    0xa0 => EventKind::PolyPressure {
+                        channel,
+                        key: data[0],
+                        pressure: data[1],
+                    },
+                    0xb0 => EventKind::Controller {
+                        channel,
+                        controller: data[0],
+                        value: data[1],
+                    },
+                    0xc0 => EventKind::ProgramChange {
+                        channel,
+                        program: data[0],
+                    },
+                    0xd0 => EventKind::ChannelPressure {
+                        channel,
+                        pressure: data[0],
+                    },
+                    _ => EventKind::PitchBend {
+                        channel,
+                        value: data[0] as u16 | (data[1] as u16) << 7,
+                    },
+                }
+            }
+            0xf0 | 0xf7 => {
+                running_status = None;
+                let len = parser.vlq()? as usize;
+                let data = parser.take(len)?.to_vec();
+                if status == 0xf0 {
+                    EventKind::SysEx(data)
+                } else {
+                    EventKind::Escape(data)
+                }
+            }
+            0xff => {
+                running_status = None;
+                let kind = parser.u8()?;
+                let len = parser.vlq()? as usize;
+                EventKind::Meta(read_meta(kind, parser.take(len)?))
+            }
+            _ => {
+                return Err(MidiError::Corrupt {
+                    offset: offset as u64,
+                    reason: format!("status byte {:#04x} has no place in a file", status),
+                })
+            }
+        };
+        let end = kind == EventKind::Meta(Meta::EndOfTrack);
+        events.push(Event { tick, kind });
+        if end {
+            break;
+        }
+    }
+    Ok(events)
+}
+
+impl Smf {
+    /// An empty file of one track.
+    pub fn new(timing: Timing) -> Smf {
+        Smf {
+            format: Format::SingleTrack,
+            timing,
+            tracks: vec![Vec::new()],
+        }
+    }
+
+    pub fn from_file(filename: &str) -> Result<Smf, MidiError> {
+        let f = File::open(filename)?;
+        Smf::from_reader(BufReader::new(f))
+    }
+
+    /// Reads a format 0 or 1 file. Chunks other than the header and tracks
+    /// are skipped, as are bytes after a track's end of track event.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Smf, MidiError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut parser = Parser {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        let id = parser.take(4)?;
+        if id != b"MThd" {
+            return Err(MidiError::BadMagic {
+                offset: 0,
+                expected: "MThd",
+                found: String::from_utf8_lossy(id).to_string(),
+            });
+        }
+        let size = parser.u32()? as usize;
+        let mut header = Parser {
+            bytes: parser.take(size)?,
+            pos: 0,
+        };
+        let format = match header.u16().map_err(|e| e.shifted(8))? {
+            0 => Format::SingleTrack,
+            1 => Format::Parallel,
+            format => {
+                return Err(MidiError::Unsupported {
+                    offset: 8,
+                    reason: format!("format {} files are not supported", format),
+                })
+            }
+        };
+        let ntracks = header.u16().map_err(|e| e.shifted(8))?;
+        let division = header.u16().map_err(|e| e.shifted(8))?;
+        let timing = if division & 0x8000 == 0 {
+            Timing::Metrical(division)
+        } else {
+            // the high byte is the frame rate, negated
+            let fps = -((division >> 8) as u8 as i8 as i16);
+            if ![24, 25, 29, 30].contains(&fps) {
+                return Err(MidiError::Unsupported {
+                    offset: 12,
+                    reason: format!("{} frames per second", fps),
+                });
+            }
+            Timing::Timecode {
+                fps: fps as u8,
+                ticks_per_frame: division as u8,
+            }
+        };
+
+        let mut tracks = Vec::with_capacity(ntracks as usize);
+        while tracks.len() < ntracks as usize {
+            let offset = parser.pos;
+            let id = parser.take(4)?;
+            let size = parser.u32()? as usize;
+            let start = parser.pos;
+            let chunk = parser.take(size).map_err(|_| MidiError::Truncated {
+                offset: offset as u64,
+                expected: 8 + size as u64,
+                found: (8 + parser.bytes.len() - start) as u64,
+            })?;
+            if id != b"MTrk" {
+                continue;
+            }
+            let mut track = Parser {
+                bytes: chunk,
+                pos: 0,
+            };
+            // offsets in the chunk are relative to its start
+            let events = read_track(&mut track).map_err(|e| e.shifted(start as u64))?;
+            tracks.push(events);
+        }
+        Ok(Smf {
+            format,
+            timing,
+            tracks,
+        })
+    }
+
+    /// The tempo changes of every track. In a format 1 file they should all
+    /// be in the first track, but some tools put them elsewhere.
+    pub fn tempo_map(&self) -> TempoMap {
+        let mut tempos: Vec<(u64, u32)> = self
+            .tracks
+            .iter()
+            .flatten()
+            .filter_map(|event| match event.kind {
+                EventKind::Meta(Meta::Tempo(tempo)) => Some((event.tick, tempo)),
+                _ => None,
+            })
+            .collect();
+        tempos.sort_by_key(|&(tick, _)| tick);
+
+        let ppq = match self.timing {
+            Timing::Metrical(ppq) => ppq.max(1) as f64,
+            Timing::Timecode { .. } => 1.0,
+        };
+        let mut changes = Vec::with_capacity(tempos.len());
+        let (mut last_tick, mut seconds, mut last_tempo) = (0, 0.0, DEFAULT_TEMPO);
+        for (tick, tempo) in tempos {
+            seconds += (tick - last_tick) as f64 * last_tempo as f64 / 1e6 / ppq;
+            changes.push((tick, seconds, tempo));
+            last_tick = tick;
+            last_tempo = tempo;
+        }
+        TempoMap {
+            timing: self.timing,
+            changes,
+        }
+    }
+
+    /// Every event of every track in the order they play, with the time in
+    /// seconds at which they do. Events at the same tick keep track order.
+    pub fn timeline(&self) -> Vec<TimedEvent> {
+        let tempo_map = self.tempo_map();
+        let mut events: Vec<TimedEvent> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .flat_map(|(track, events)| {
+                events.iter().map(move |event| TimedEvent {
+                    seconds: 0.0,
+                    tick: event.tick,
+                    track,
+                    kind: event.kind.clone(),
+                })
+            })
+            .collect();
+        events.sort_by_key(|event| event.tick);
+        for event in events.iter_mut() {
+            event.seconds = tempo_map.seconds(event.tick);
+        }
+        events
+    }
+
+    pub fn write(&self, filename: &str) -> Result<(), MidiError> {
+        let f = File::create(filename)?;
+        let mut writer = BufWriter::new(f);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the file with running status. A track without an end of track
+    /// event gets one after its last event.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), MidiError> {
+        if self.format == Format::SingleTrack && self.tracks.len() != 1 {
+            return Err(MidiError::InvalidInput(format!(
+                "format 0 files hold one track, not {}",
+                self.tracks.len()
+            )));
+        }
+        if self.tracks.len() > u16::MAX as usize {
+            return Err(MidiError::InvalidInput(format!(
+                "{} tracks is more than a file can hold",
+                self.tracks.len()
+            )));
+        }
+        let division = match self.timing {
+            Timing::Metrical(ppq) if ppq < 0x8000 => ppq,
+            Timing::Metrical(ppq) => {
+                return Err(MidiError::InvalidInput(format!(
+                    "{} ticks per quarter note is more than a file can hold",
+                    ppq
+                )))
+            }
+            Timing::Timecode {
+                fps,
+                ticks_per_frame,
+            } if [24, 25, 29, 30].contains(&fps) => {
+                (((-(fps as i8)) as u8 as u16) << 8) | ticks_per_frame as u16
+            }
+            Timing::Timecode { fps, .. } => {
+                return Err(MidiError::InvalidInput(format!(
+                    "{} frames per second is not an SMPTE rate",
+                    fps
+                )))
+            }
+        };
+
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6u32.to_be_bytes())?;
+        let format: u16 = match self.format {
+            Format::SingleTrack => 0,
+            Format::Parallel => 1,
+        };
+        writer.write_all(&format.to_be_bytes())?;
+        writer.write_all(&(self.tracks.len() as u16).to_be_bytes())?;
+        writer.write_all(&division.to_be_bytes())?;
+
+        for (n, events) in self.tracks.iter().enumerate() {
+            let chunk = Smf::track_bytes(events)
+                .map_err(|reason| MidiError::InvalidInput(format!("track {}: {}", n, reason)))?;
+            writer.write_all(b"MTrk")?;
+            writer.write_all(&(chunk.len() as u32).to_be_bytes())?;
+            writer.write_all(&chunk)?;
+        }
+        Ok(())
+    }
+
+    fn track_bytes(events: &[Event]) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        let mut tick = 0;
+        let mut running_status = None;
+        let end = Event {
+            tick: events.last().map_or(0, |event| event.tick),
+            kind: EventKind::Meta(Meta::EndOfTrack),
+        };
+        let needs_end = events.last().map(|event| &event.kind) != Some(&end.kind);
+        let events = events.iter().chain(Some(&end).filter(|_| needs_end));
+        for event in events {
+            if event.tick < tick {
+                return Err(format!(
+                    "event at tick {} follows tick {}",
+                    event.tick, tick
+                ));
+            }
+            let delta = event.tick - tick;
+            if delta >= 1 << 28 {
+                return Err(format!("{} ticks between events is too long", delta));
+            }
+            write_vlq(&mut bytes, delta as u32).unwrap();
+            tick = event.tick;
+
+            if let Some((kind, channel, data)) = event.kind.channel_message() {
+                if channel >= 16 || data.iter().any(|&b| b >= 0x80) {
+                    return Err(format!("{:?} does not fit in a message", event.kind));
+                }
+                let status = kind | channel;
+                if running_status != Some(status) {
+                    bytes.push(status);
+                    running_status = Some(status);
+                }
+                bytes.extend_from_slice(&data);
+                continue;
+            }
+            running_status = None;
+            let (prefix, data) = match &event.kind {
+                EventKind::SysEx(data) => (vec![0xf0], data.clone()),
+                EventKind::Escape(data) => (vec![0xf7], data.clone()),
+                EventKind::Meta(meta) => {
+                    let (kind, data) = meta_bytes(meta);
+                    (vec![0xff, kind], data)
+                }
+                _ => unreachable!(),
+            };
+            bytes.extend_from_slice(&prefix);
+            write_vlq(&mut bytes, data.len() as u32).unwrap();
+            bytes.extend_from_slice(&data);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{Event, EventKind, Format, Meta, MidiError, Smf, Timing};
+    use std::io::Cursor;
+
+    /// A format 1 file with a tempo track and a note track using running
+    /// status, as a sequencer would write it.
+    fn file() -> Vec<u8> {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60".to_vec();
+        let tempo_track = [
+            0x00, 0xff, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08, // 6/8
+            0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, // 120 bpm
+            0x81, 0x40, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, // 60 bpm at tick 192
+            0x00, 0xff, 0x05, 0x04, b'd', b'r', b'o', b'p', // marker
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+        let note_track = [
+            0x00, 0xc0, 0x05, // program 5
+            0x00, 0x90, 0x3c, 0x64, // middle C on
+            0x60, 0x40, 0x64, // E on, running status
+            0x60, 0x3c, 0x00, // middle C off as a note on
+            0x81, 0x40, 0x80, 0x40, 0x40, // E off
+            0x00, 0xe0, 0x00, 0x40, // no pitch bend
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+        for track in [&tempo_track[..], &note_track[..]].iter() {
+            bytes.extend_from_slice(b"MTrk");
+            bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(track);
+        }
+        bytes
+    }
+
+    #[test]
+    fn timeline() {
+        let smf = Smf::from_reader(Cursor::new(file())).unwrap();
+        assert_eq!(smf.format, Format::Parallel);
+        assert_eq!(smf.timing, Timing::Metrical(96));
+        assert_eq!(
+            smf.tracks[0][0].kind,
+            EventKind::Meta(Meta::TimeSignature {
+                numerator: 6,
+                denominator: 8,
+                clocks_per_click: 24,
+                thirty_seconds_per_quarter: 8,
+            })
+        );
+        assert_eq!(
+            smf.tracks[1][2],
+            Event {
+                tick: 96,
+                kind: EventKind::NoteOn {
+                    channel: 0,
+                    key: 0x40,
+                    velocity: 100
+                }
+            }
+        );
+
+        let timeline = smf.timeline();
+        assert_eq!(timeline.len(), 12);
+        let times: Vec<(u64, f64)> = timeline
+            .iter()
+            .filter(|event| event.track == 1)
+            .map(|event| (event.tick, event.seconds))
+            .collect();
+        // 96 ticks a quarter note at 120 bpm until tick 192, then 60 bpm
+        assert_eq!(
+            times,
+            vec![
+                (0, 0.0),
+                (0, 0.0),
+                (96, 0.5),
+                (192, 1.0),
+                (384, 3.0),
+                (384, 3.0),
+                (384, 3.0)
+            ]
+        );
+        let tempo_map = smf.tempo_map();
+        assert_eq!(tempo_map.bpm(100), 120.0);
+        assert_eq!(tempo_map.bpm(192), 60.0);
+    }
+
+    #[test]
+    fn written_bytes_match() {
+        let smf = Smf::from_reader(Cursor::new(file())).unwrap();
+        let mut bytes = Vec::new();
+        smf.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, file());
+    }
+
+    #[test]
+    fn end_of_track_is_added() {
+        let mut smf = Smf::new(Timing::Timecode {
+            fps: 25,
+            ticks_per_frame: 40,
+        });
+        smf.tracks[0].push(Event {
+            tick: 500,
+            kind: EventKind::SysEx(vec![0x7e, 0x7f, 0x09, 0x01, 0xf7]),
+        });
+        let mut bytes = Vec::new();
+        smf.write_to(&mut bytes).unwrap();
+
+        let read = Smf::from_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.timing, smf.timing);
+        assert_eq!(read.tracks[0].len(), 2);
+        assert_eq!(read.tracks[0][1].kind, EventKind::Meta(Meta::EndOfTrack));
+        assert_eq!(read.timeline()[0].seconds, 0.5);
+    }
+
+    #[test]
+    fn only_smpte_frame_rates() {
+        // -128 frames per second does not negate into an i8
+        let bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x00\x80\x28".to_vec();
+        match Smf::from_reader(Cursor::new(bytes)) {
+            Err(MidiError::Unsupported { offset, .. }) => assert_eq!(offset, 12),
+            other => panic!("{:?}", other),
+        }
+
+        for &fps in &[128, 0, 60] {
+            let smf = Smf::new(Timing::Timecode {
+                fps,
+                ticks_per_frame: 40,
+            });
+            assert!(matches!(
+                smf.write_to(&mut Vec::new()),
+                Err(MidiError::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn running_status_needs_a_status() {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60".to_vec();
+        bytes.extend_from_slice(b"MTrk\x00\x00\x00\x04\x00\x3c\x64\x00");
+        match Smf::from_reader(Cursor::new(bytes)) {
+            Err(MidiError::Corrupt { offset, .. }) => assert_eq!(offset, 23),
+            other => panic!("{:?}", other),
+        }
+    }
+}
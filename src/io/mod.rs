@@ -1 +1,12 @@
+pub mod aiff;
+mod error;
+pub mod flac;
+pub mod id3;
+pub mod midi;
+pub mod raw;
+pub mod sample;
+mod source;
 pub mod wav;
+
+pub use error::ContainerError;
+pub use source::{open, open_reader, AudioError, AudioSource};
@@ -0,0 +1,206 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use byteorder::{BigEndian, LittleEndian};
+
+use super::error::ContainerError;
+use super::sample::{SampleFormat, Samples};
+use super::wav::{WavSpec, WAV};
+
+/// Errors reading or writing raw PCM streams.
+pub type RawError = ContainerError;
+
+/// The byte order of each sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// Parses `le` or `be`.
+    pub fn parse(s: &str) -> Option<Endianness> {
+        match s {
+            "le" => Some(Endianness::Little),
+            "be" => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a sample format by its name in lower case: `u8`, `i16`, `i24`,
+/// `i32`, `f32` or `f64`.
+pub fn parse_format(s: &str) -> Option<SampleFormat> {
+    match s {
+        "u8" => Some(SampleFormat::U8),
+        "i16" => Some(SampleFormat::I16),
+        "i24" => Some(SampleFormat::I24),
+        "i32" => Some(SampleFormat::I32),
+        "f32" => Some(SampleFormat::F32),
+        "f64" => Some(SampleFormat::F64),
+        _ => None,
+    }
+}
+
+/// What a headerless stream of interleaved samples holds, which the file
+/// cannot say for itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub format: SampleFormat,
+    pub endianness: Endianness,
+    pub nchannels: u16,
+    pub sample_rate: u32,
+}
+
+impl Layout {
+    fn check(&self) -> Result<(), RawError> {
+        if self.nchannels == 0 || self.sample_rate == 0 {
+            return Err(RawError::InvalidInput(format!(
+                "{} channels at {} Hz",
+                self.nchannels, self.sample_rate
+            )));
+        }
+        // the frame size and byte rate must fit the WAV header
+        WavSpec {
+            sample_rate: self.sample_rate,
+            nchannels: self.nchannels,
+            format: self.format,
+        }
+        .byte_rate()?;
+        Ok(())
+    }
+}
+
+pub fn from_file(filename: &str, layout: Layout) -> Result<WAV, RawError> {
+    let f = File::open(filename)?;
+    from_reader(BufReader::new(f), layout)
+}
+
+/// Reads a headerless PCM stream laid out as `layout` into the same
+/// in-memory form as a WAV file. A partial frame at the end, as left by a
+/// logger that lost power, is dropped.
+pub fn from_reader<R: Read>(mut reader: R, layout: Layout) -> Result<WAV, RawError> {
+    layout.check()?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let frame_size = layout.format.bytes_per_sample() * layout.nchannels as usize;
+    let bytes = &bytes[..bytes.len() - bytes.len() % frame_size];
+    let signal = match layout.endianness {
+        Endianness::Little => Samples::from_bytes::<LittleEndian>(layout.format, bytes),
+        Endianness::Big => Samples::from_bytes::<BigEndian>(layout.format, bytes),
+    };
+    WAV::new(layout.sample_rate, layout.nchannels, signal)
+}
+
+pub fn write(
+    wav: &WAV,
+    filename: &str,
+    format: SampleFormat,
+    endianness: Endianness,
+) -> Result<(), RawError> {
+    let f = File::create(filename)?;
+    let mut writer = BufWriter::new(f);
+    write_to(wav, &mut writer, format, endianness)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the samples of `wav` and nothing else, converted to `format` if
+/// they are stored in another.
+pub fn write_to<W: Write>(
+    wav: &WAV,
+    writer: &mut W,
+    format: SampleFormat,
+    endianness: Endianness,
+) -> Result<(), RawError> {
+    let converted;
+    let signal = if wav.signal.format() == format {
+        &wav.signal
+    } else {
+        converted = Samples::from_f64(format, &wav.signal.to_f64());
+        &converted
+    };
+    match endianness {
+        Endianness::Little => signal.write::<LittleEndian, _>(writer)?,
+        Endianness::Big => signal.write::<BigEndian, _>(writer)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{from_reader, write_to, Endianness, Layout, RawError};
+    use crate::io::sample::{SampleFormat, Samples};
+    use crate::io::wav::WAV;
+    use std::io::Cursor;
+
+    #[test]
+    fn both_byte_orders() {
+        let wav = WAV::new(16000, 2, Samples::I24(vec![1, -2, 0x123456, -0x123456])).unwrap();
+        for &endianness in &[Endianness::Little, Endianness::Big] {
+            let mut bytes = Vec::new();
+            write_to(&wav, &mut bytes, SampleFormat::I24, endianness).unwrap();
+            assert_eq!(bytes.len(), 12);
+            let first = if endianness == Endianness::Little {
+                [1, 0, 0]
+            } else {
+                [0, 0, 1]
+            };
+            assert_eq!(bytes[..3], first);
+
+            // and half a frame a logger cut off
+            bytes.extend_from_slice(&[7, 7, 7]);
+            let layout = Layout {
+                format: SampleFormat::I24,
+                endianness,
+                nchannels: 2,
+                sample_rate: 16000,
+            };
+            let raw = from_reader(Cursor::new(bytes), layout).unwrap();
+            assert_eq!(raw.signal, wav.signal);
+            assert_eq!(raw.fmt_header.sample_rate, 16000);
+            assert_eq!(raw.nframes(), 2);
+        }
+    }
+
+    #[test]
+    fn converts_on_write() {
+        let wav = WAV::new(8000, 1, Samples::I16(vec![0x4000, -0x8000])).unwrap();
+        let mut bytes = Vec::new();
+        write_to(&wav, &mut bytes, SampleFormat::U8, Endianness::Big).unwrap();
+        assert_eq!(bytes, vec![0xc0, 0x00]);
+    }
+
+    #[test]
+    fn no_channels() {
+        let layout = Layout {
+            format: SampleFormat::I16,
+            endianness: Endianness::Little,
+            nchannels: 0,
+            sample_rate: 44100,
+        };
+        assert!(matches!(
+            from_reader(Cursor::new(vec![0; 4]), layout),
+            Err(RawError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_layout() {
+        for &(format, nchannels, sample_rate) in &[
+            (SampleFormat::F64, 10000, 8000),
+            (SampleFormat::F64, 2, 1 << 30),
+        ] {
+            let layout = Layout {
+                format,
+                endianness: Endianness::Little,
+                nchannels,
+                sample_rate,
+            };
+            assert!(matches!(
+                from_reader(Cursor::new(vec![0; 16]), layout),
+                Err(RawError::InvalidInput(_))
+            ));
+        }
+    }
+}
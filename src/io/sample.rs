@@ -0,0 +1,352 @@
+use std::io::{self, Write};
+
+use byteorder::{ByteOrder, WriteBytesExt};
+
+/// The encodings a sample can take on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            SampleFormat::U8 => 8,
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    pub fn bytes_per_sample(self) -> usize {
+        self.bits_per_sample() as usize / 8
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, SampleFormat::F32 | SampleFormat::F64)
+    }
+}
+
+/// Which part of a multichannel stream to analyse or display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelSelection {
+    /// a single zero indexed channel
+    Channel(usize),
+    /// the mean of all channels
+    Downmix,
+}
+
+impl ChannelSelection {
+    /// The selected value of one frame of normalized samples.
+    pub fn pick(self, frame: &[f64]) -> f64 {
+        match self {
+            ChannelSelection::Channel(c) => frame[c],
+            ChannelSelection::Downmix => frame.iter().sum::<f64>() / frame.len() as f64,
+        }
+    }
+}
+
+const I24_MAX: i32 = (1 << 23) - 1;
+const I24_MIN: i32 = -(1 << 23);
+
+/// Interleaved samples kept in the type they are stored as on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Samples {
+    U8(Vec<u8>),
+    I16(Vec<i16>),
+    /// packed 24 bit samples, sign extended into an `i32`
+    I24(Vec<i32>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+impl Samples {
+    pub fn format(&self) -> SampleFormat {
+        match self {
+            Samples::U8(_) => SampleFormat::U8,
+            Samples::I16(_) => SampleFormat::I16,
+            Samples::I24(_) => SampleFormat::I24,
+            Samples::I32(_) => SampleFormat::I32,
+            Samples::F32(_) => SampleFormat::F32,
+            Samples::F64(_) => SampleFormat::F64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::U8(x) => x.len(),
+            Samples::I16(x) => x.len(),
+            Samples::I24(x) | Samples::I32(x) => x.len(),
+            Samples::F32(x) => x.len(),
+            Samples::F64(x) => x.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Decodes `bytes` as a run of `format` samples in byte order `B`. A
+    /// trailing partial sample is ignored.
+    pub fn from_bytes<B: ByteOrder>(format: SampleFormat, bytes: &[u8]) -> Samples {
+        let chunks = bytes.chunks_exact(format.bytes_per_sample());
+        match format {
+            SampleFormat::U8 => Samples::U8(bytes.to_vec()),
+            SampleFormat::I16 => Samples::I16(chunks.map(B::read_i16).collect()),
+            SampleFormat::I24 => Samples::I24(chunks.map(B::read_i24).collect()),
+            SampleFormat::I32 => Samples::I32(chunks.map(B::read_i32).collect()),
+            SampleFormat::F32 => Samples::F32(chunks.map(B::read_f32).collect()),
+            SampleFormat::F64 => Samples::F64(chunks.map(B::read_f64).collect()),
+        }
+    }
+
+    /// Encodes every sample in byte order `B`.
+    pub fn write<B: ByteOrder, W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Samples::U8(x) => writer.write_all(x)?,
+            Samples::I16(x) => {
+                for s in x {
+                    writer.write_i16::<B>(*s)?
+                }
+            }
+            Samples::I24(x) => {
+                for s in x {
+                    writer.write_i24::<B>(*s)?
+                }
+            }
+            Samples::I32(x) => {
+                for s in x {
+                    writer.write_i32::<B>(*s)?
+                }
+            }
+            Samples::F32(x) => {
+                for s in x {
+                    writer.write_f32::<B>(*s)?
+                }
+            }
+            Samples::F64(x) => {
+                for s in x {
+                    writer.write_f64::<B>(*s)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The `index`th sample normalized to [-1.0, 1.0).
+    pub fn get_f64(&self, index: usize) -> f64 {
+        match self {
+            Samples::U8(x) => (x[index] as f64 - 128.0) / 128.0,
+            Samples::I16(x) => x[index] as f64 / 32768.0,
+            Samples::I24(x) => x[index] as f64 / 8_388_608.0,
+            Samples::I32(x) => x[index] as f64 / 2_147_483_648.0,
+            Samples::F32(x) => x[index] as f64,
+            Samples::F64(x) => x[index],
+        }
+    }
+
+    /// Every sample normalized to [-1.0, 1.0).
+    pub fn to_f64(&self) -> Vec<f64> {
+        (0..self.len()).map(|n| self.get_f64(n)).collect()
+    }
+
+    /// Every sample normalized to [-1.0, 1.0) in single precision.
+    pub fn to_f32(&self) -> Vec<f32> {
+        (0..self.len()).map(|n| self.get_f64(n) as f32).collect()
+    }
+
+    /// Quantizes normalized samples to `format`, clipping anything outside
+    /// [-1.0, 1.0).
+    pub fn from_f64(format: SampleFormat, samples: &[f64]) -> Samples {
+        fn quantize(x: f64, scale: f64, min: f64, max: f64) -> f64 {
+            (x * scale).round().clamp(min, max)
+        }
+        let iter = samples.iter();
+        match format {
+            SampleFormat::U8 => Samples::U8(
+                iter.map(|x| (quantize(*x, 128.0, -128.0, 127.0) + 128.0) as u8)
+                    .collect(),
+            ),
+            SampleFormat::I16 => Samples::I16(
+                iter.map(|x| quantize(*x, 32768.0, i16::MIN as f64, i16::MAX as f64) as i16)
+                    .collect(),
+            ),
+            SampleFormat::I24 => Samples::I24(
+                iter.map(|x| quantize(*x, 8_388_608.0, I24_MIN as f64, I24_MAX as f64) as i32)
+                    .collect(),
+            ),
+            SampleFormat::I32 => Samples::I32(
+                iter.map(|x| {
+                    quantize(*x, 2_147_483_648.0, i32::MIN as f64, i32::MAX as f64) as i32
+                })
+                .collect(),
+            ),
+            SampleFormat::F32 => Samples::F32(iter.map(|x| *x as f32).collect()),
+            SampleFormat::F64 => Samples::F64(samples.to_vec()),
+        }
+    }
+
+    /// The samples of one channel of an interleaved stream, normalized.
+    pub fn channel(&self, nchannels: usize, channel: usize) -> impl Iterator<Item = f64> + '_ {
+        assert!(
+            channel < nchannels,
+            "channel {} out of range for {} channels",
+            channel,
+            nchannels
+        );
+        (channel..self.len())
+            .step_by(nchannels)
+            .map(move |n| self.get_f64(n))
+    }
+
+    /// The normalized samples of each whole frame of an interleaved stream.
+    pub fn frames(&self, nchannels: usize) -> impl Iterator<Item = Vec<f64>> + '_ {
+        (0..self.len() / nchannels).map(move |frame| {
+            (0..nchannels)
+                .map(|c| self.get_f64(frame * nchannels + c))
+                .collect()
+        })
+    }
+
+    /// Splits an interleaved stream into one normalized vector per channel.
+    pub fn deinterleave(&self, nchannels: usize) -> Vec<Vec<f64>> {
+        (0..nchannels)
+            .map(|c| self.channel(nchannels, c).collect())
+            .collect()
+    }
+
+    /// The mean of every channel in each frame.
+    pub fn downmix(&self, nchannels: usize) -> Vec<f64> {
+        self.frames(nchannels)
+            .map(|frame| frame.iter().sum::<f64>() / nchannels as f64)
+            .collect()
+    }
+
+    /// Interleaves equal length normalized channels and quantizes them to
+    /// `format`.
+    pub fn interleave(format: SampleFormat, channels: &[Vec<f64>]) -> Samples {
+        let nframes = channels.first().map_or(0, |c| c.len());
+        assert!(
+            channels.iter().all(|c| c.len() == nframes),
+            "channels have different lengths"
+        );
+        let interleaved: Vec<f64> = (0..nframes)
+            .flat_map(|frame| channels.iter().map(move |c| c[frame]))
+            .collect();
+        Samples::from_f64(format, &interleaved)
+    }
+
+    /// The normalized samples of the channel or mix picked by `selection`.
+    pub fn select(&self, nchannels: usize, selection: ChannelSelection) -> Vec<f64> {
+        match selection {
+            ChannelSelection::Channel(c) => self.channel(nchannels, c).collect(),
+            ChannelSelection::Downmix => self.downmix(nchannels),
+        }
+    }
+}
+
+#[cfg(test)]
+mod channels {
+    use super::{ChannelSelection, SampleFormat, Samples};
+
+    fn stereo() -> Samples {
+        Samples::F64(vec![0.5, -0.5, 0.25, -0.25, 1.0, 0.0])
+    }
+
+    #[test]
+    fn deinterleave() {
+        let expected = vec![vec![0.5, 0.25, 1.0], vec![-0.5, -0.25, 0.0]];
+        assert_eq!(expected, stereo().deinterleave(2));
+    }
+
+    #[test]
+    fn frames() {
+        let frames: Vec<Vec<f64>> = stereo().frames(2).collect();
+        assert_eq!(
+            frames,
+            vec![vec![0.5, -0.5], vec![0.25, -0.25], vec![1.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn select() {
+        let samples = stereo();
+        assert_eq!(
+            samples.select(2, ChannelSelection::Channel(1)),
+            vec![-0.5, -0.25, 0.0]
+        );
+        assert_eq!(
+            samples.select(2, ChannelSelection::Downmix),
+            vec![0.0, 0.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn interleave_is_inverse_of_deinterleave() {
+        let samples = stereo();
+        let channels = samples.deinterleave(2);
+        assert_eq!(samples, Samples::interleave(SampleFormat::F64, &channels));
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{SampleFormat, Samples};
+    use byteorder::{BigEndian, LittleEndian};
+
+    const FORMATS: [SampleFormat; 6] = [
+        SampleFormat::U8,
+        SampleFormat::I16,
+        SampleFormat::I24,
+        SampleFormat::I32,
+        SampleFormat::F32,
+        SampleFormat::F64,
+    ];
+
+    #[test]
+    fn bytes_round_trip() {
+        let normalized = vec![-1.0, -0.5, 0.0, 0.25, 0.5];
+        for format in FORMATS.iter() {
+            let samples = Samples::from_f64(*format, &normalized);
+            let mut le = Vec::new();
+            samples.write::<LittleEndian, _>(&mut le).unwrap();
+            assert_eq!(le.len(), normalized.len() * format.bytes_per_sample());
+            assert_eq!(samples, Samples::from_bytes::<LittleEndian>(*format, &le));
+
+            let mut be = Vec::new();
+            samples.write::<BigEndian, _>(&mut be).unwrap();
+            assert_eq!(samples, Samples::from_bytes::<BigEndian>(*format, &be));
+        }
+    }
+
+    #[test]
+    fn normalized_round_trip() {
+        let normalized = vec![-1.0, -0.5, 0.0, 0.25, 0.5];
+        for format in FORMATS.iter() {
+            let samples = Samples::from_f64(*format, &normalized);
+            assert_eq!(normalized, samples.to_f64());
+        }
+    }
+
+    #[test]
+    fn clips_out_of_range() {
+        let samples = Samples::from_f64(SampleFormat::I16, &[2.0, -2.0]);
+        assert_eq!(samples, Samples::I16(vec![i16::MAX, i16::MIN]));
+        let samples = Samples::from_f64(SampleFormat::U8, &[1.0, -1.0]);
+        assert_eq!(samples, Samples::U8(vec![255, 0]));
+    }
+
+    #[test]
+    fn packed_24_bit() {
+        let bytes = [0xff, 0xff, 0x7f, 0x00, 0x00, 0x80];
+        let samples = Samples::from_bytes::<LittleEndian>(SampleFormat::I24, &bytes);
+        assert_eq!(samples, Samples::I24(vec![8_388_607, -8_388_608]));
+    }
+}
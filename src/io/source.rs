@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use super::aiff;
+use super::error::ContainerError;
+use super::flac;
+use super::id3;
+use super::midi::Smf;
+use super::wav::{Cues, TrackMetadata, WavReader, WAV};
+use crate::synth::Synth;
+
+/// Errors opening or reading an audio file of any format cldj knows.
+pub type AudioError = ContainerError;
+
+/// A stream of interleaved frames, whatever container they come from.
+pub trait AudioSource {
+    fn sample_rate(&self) -> u32;
+
+    fn nchannels(&self) -> usize;
+
+    /// The number of whole frames in the stream.
+    fn nframes(&self) -> u64;
+
+    /// The index of the next frame to be read.
+    fn position(&self) -> u64;
+
+    /// Fills `buf` with normalized interleaved samples, returning the number
+    /// of whole frames read. Zero means the end of the stream was reached.
+    fn read_frames(&mut self, buf: &mut [f64]) -> Result<usize, AudioError>;
+
+    /// Moves to frame `frame`; seeking past the end leaves the source at the
+    /// end.
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError>;
+
+    fn metadata(&self) -> &TrackMetadata;
+
+    fn cues(&self) -> &Cues;
+}
+
+impl<R: Read + Seek> AudioSource for WavReader<R> {
+    fn sample_rate(&self) -> u32 {
+        WavReader::sample_rate(self)
+    }
+
+    fn nchannels(&self) -> usize {
+        WavReader::nchannels(self)
+    }
+
+    fn nframes(&self) -> u64 {
+        WavReader::nframes(self)
+    }
+
+    fn position(&self) -> u64 {
+        WavReader::position(self)
+    }
+
+    fn read_frames(&mut self, buf: &mut [f64]) -> Result<usize, AudioError> {
+        WavReader::read_frames(self, buf)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        WavReader::seek(self, frame)
+    }
+
+    fn metadata(&self) -> &TrackMetadata {
+        &self.metadata
+    }
+
+    fn cues(&self) -> &Cues {
+        &self.cues
+    }
+}
+
+impl AudioSource for WAV {
+    fn sample_rate(&self) -> u32 {
+        self.fmt_header.sample_rate
+    }
+
+    fn nchannels(&self) -> usize {
+        WAV::nchannels(self)
+    }
+
+    fn nframes(&self) -> u64 {
+        WAV::nframes(self) as u64
+    }
+
+    fn position(&self) -> u64 {
+        self.cursor
+    }
+
+    fn read_frames(&mut self, buf: &mut [f64]) -> Result<usize, AudioError> {
+        let nchannels = WAV::nchannels(self);
+        let nframes =
+            (buf.len() / nchannels).min((AudioSource::nframes(self) - self.cursor) as usize);
+        let start = self.cursor as usize * nchannels;
+        for (n, x) in buf.iter_mut().take(nframes * nchannels).enumerate() {
+            *x = self.signal.get_f64(start + n);
+        }
+        self.cursor += nframes as u64;
+        Ok(nframes)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        self.cursor = frame.min(AudioSource::nframes(self));
+        Ok(())
+    }
+
+    fn metadata(&self) -> &TrackMetadata {
+        &self.metadata
+    }
+
+    fn cues(&self) -> &Cues {
+        &self.cues
+    }
+}
+
+/// The sample rate MIDI files are rendered at.
+const MIDI_SAMPLE_RATE: u32 = 44100;
+
+/// Opens `filename` with the decoder its first bytes call for, whatever its
+/// extension.
+pub fn open(filename: &str) -> Result<Box<dyn AudioSource>, AudioError> {
+    let f = File::open(filename)?;
+    open_reader(BufReader::new(f))
+}
+
+/// Picks a decoder by the magic bytes at the start of `reader`. WAV files
+/// are streamed; AIFF and FLAC files are decoded into memory, and MIDI files
+/// are rendered through the built-in synth.
+pub fn open_reader<R: Read + Seek + 'static>(
+    mut reader: R,
+) -> Result<Box<dyn AudioSource>, AudioError> {
+    let mut magic = Vec::new();
+    reader.by_ref().take(12).read_to_end(&mut magic)?;
+    // an ID3v2 tag may come before a FLAC stream
+    if magic.starts_with(b"ID3") && magic.len() >= 10 {
+        // an ID3v2.4 footer repeats the header after the tag
+        let footer = if magic[5] & 0x10 != 0 { 10 } else { 0 };
+        let size = id3::syncsafe(&magic[6..10]) + footer;
+        reader.seek(SeekFrom::Start(10 + size as u64))?;
+        let mut after = Vec::new();
+        reader.by_ref().take(4).read_to_end(&mut after)?;
+        if after == b"fLaC" {
+            magic = after;
+        }
+    }
+    reader.seek(SeekFrom::Start(0))?;
+
+    let kind = (magic.get(0..4), magic.get(8..12));
+    let source: Box<dyn AudioSource> = match kind {
+        (Some(b"RIFF"), Some(b"WAVE"))
+        | (Some(b"RF64"), Some(b"WAVE"))
+        | (Some(b"BW64"), Some(b"WAVE")) => Box::new(WavReader::new(reader)?),
+        (Some(b"FORM"), Some(b"AIFF")) | (Some(b"FORM"), Some(b"AIFC")) => {
+            Box::new(aiff::from_reader(reader)?)
+        }
+        (Some(b"fLaC"), _) => Box::new(flac::from_reader(reader)?),
+        (Some(b"MThd"), _) => {
+            let smf = Smf::from_reader(reader)?;
+            Box::new(Synth::new(MIDI_SAMPLE_RATE).render_wav(&smf)?)
+        }
+        _ => return Err(AudioError::UnknownFormat { found: magic }),
+    };
+    Ok(source)
+}
+
+#[cfg(test)]
+mod sniffing {
+    use super::{open_reader, AudioError, AudioSource};
+    use crate::io::aiff::{self, Compression};
+    use crate::io::flac;
+    use crate::io::id3;
+    use crate::io::sample::{SampleFormat, Samples};
+    use crate::io::wav::WAV;
+    use std::io::Cursor;
+
+    fn wav() -> WAV {
+        let signal = [0.5, -0.5, 0.25, -0.25, 0.0, 0.75, -1.0, 0.125];
+        let mut wav = WAV::new(22050, 2, Samples::from_f64(SampleFormat::I16, &signal)).unwrap();
+        wav.metadata.title = Some("Xtal".to_string());
+        wav
+    }
+
+    fn read_all(source: &mut dyn AudioSource) -> Vec<f64> {
+        let mut frames = Vec::new();
+        let mut buf = vec![0.0; 3 * source.nchannels()];
+        loop {
+            let n = source.read_frames(&mut buf).unwrap();
+            if n == 0 {
+                break frames;
+            }
+            frames.extend_from_slice(&buf[..n * source.nchannels()]);
+        }
+    }
+
+    #[test]
+    fn every_format() {
+        let expected = wav().signal.to_f64();
+        let mut files = Vec::new();
+        let mut bytes = Vec::new();
+        wav().write_to(&mut bytes).unwrap();
+        files.push(bytes);
+        let mut bytes = Vec::new();
+        aiff::write_to(&wav(), &mut bytes, Compression::Sowt).unwrap();
+        files.push(bytes);
+        let mut bytes = Vec::new();
+        flac::write_to(&wav(), &mut bytes).unwrap();
+        files.push(bytes.clone());
+        // the same FLAC file behind an ID3 tag
+        let mut tagged = id3::Tag::new(3).to_bytes();
+        tagged.extend_from_slice(&bytes);
+        files.push(tagged);
+        // and behind an ID3v2.4 tag with a footer
+        let mut tagged = id3::Tag::new(4).to_bytes();
+        tagged[5] |= 0x10;
+        let mut footer = tagged[..10].to_vec();
+        footer[..3].copy_from_slice(b"3DI");
+        tagged.extend_from_slice(&footer);
+        tagged.extend_from_slice(&bytes);
+        files.push(tagged);
+
+        for bytes in files {
+            let mut source = open_reader(Cursor::new(bytes)).unwrap();
+            assert_eq!(source.sample_rate(), 22050);
+            assert_eq!(source.nchannels(), 2);
+            assert_eq!(source.nframes(), 4);
+            assert_eq!(source.metadata().title.as_deref(), Some("Xtal"));
+            assert_eq!(read_all(source.as_mut()), expected);
+            source.seek(3).unwrap();
+            assert_eq!(source.position(), 3);
+            assert_eq!(read_all(source.as_mut()), &expected[6..]);
+        }
+    }
+
+    #[test]
+    fn midi_is_rendered() {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60".to_vec();
+        bytes
+            .extend_from_slice(b"MTrk\x00\x00\x00\x0b\x00\x90\x45\x64\x60\x45\x00\x00\xff\x2f\x00");
+        let source = open_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(source.sample_rate(), 44100);
+        assert!(source.nframes() > 44100 / 2);
+    }
+
+    #[test]
+    fn unknown_format() {
+        match open_reader(Cursor::new(b"OggS\x00\x02".to_vec())) {
+            Err(AudioError::UnknownFormat { found }) => assert_eq!(found, b"OggS\x00\x02"),
+            _ => panic!("expected an unknown format"),
+        }
+    }
+}
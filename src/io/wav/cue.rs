@@ -0,0 +1,432 @@
+use super::{put_sub_chunk, sub_chunks, zstr, Chunk};
+
+/// A marked position in the signal, such as a hot cue, from the `cue ` chunk
+/// and the labels `LIST/adtl` attaches to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CuePoint {
+    /// the id labels and loops refer to the point by
+    pub id: u32,
+    /// the frame the point marks
+    pub position: u32,
+    /// the length in frames of the region starting at `position`, from an
+    /// `ltxt` entry; 0 for a plain point
+    pub length: u32,
+    pub label: Option<String>,
+    pub note: Option<String>,
+}
+
+/// How a sampler plays a loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+    Forward,
+    PingPong,
+    Backward,
+    Other(u32),
+}
+
+impl LoopKind {
+    fn new(kind: u32) -> LoopKind {
+        match kind {
+            0 => LoopKind::Forward,
+            1 => LoopKind::PingPong,
+            2 => LoopKind::Backward,
+            kind => LoopKind::Other(kind),
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        match self {
+            LoopKind::Forward => 0,
+            LoopKind::PingPong => 1,
+            LoopKind::Backward => 2,
+            LoopKind::Other(kind) => kind,
+        }
+    }
+}
+
+/// A loop region from the `smpl` chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    /// the id of the cue point naming the loop, if any
+    pub id: u32,
+    pub kind: LoopKind,
+    /// the first frame of the loop
+    pub start: u32,
+    /// the last frame of the loop, which is played before jumping back
+    pub end: u32,
+    /// a fraction of a frame to add to `end`, in units of 1/2^32
+    pub fraction: u32,
+    /// the number of times to play the loop; 0 loops forever
+    pub play_count: u32,
+}
+
+/// The fields of a `smpl` chunk other than its loops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampler {
+    pub manufacturer: u32,
+    pub product: u32,
+    /// nanoseconds per frame
+    pub sample_period: u32,
+    /// the MIDI note played back at the original pitch
+    pub midi_unity_note: u32,
+    pub midi_pitch_fraction: u32,
+    pub smpte_format: u32,
+    pub smpte_offset: u32,
+    /// manufacturer specific data following the loops
+    pub data: Vec<u8>,
+}
+
+impl Sampler {
+    /// A sampler header with no manufacturer for a file at `sample_rate`.
+    pub fn new(sample_rate: u32) -> Sampler {
+        Sampler {
+            manufacturer: 0,
+            product: 0,
+            sample_period: 1_000_000_000 / sample_rate.max(1),
+            midi_unity_note: 60,
+            midi_pitch_fraction: 0,
+            smpte_format: 0,
+            smpte_offset: 0,
+            data: Vec::new(),
+        }
+    }
+}
+
+/// The hot cues and loops of a file, read from its `cue `, `LIST/adtl` and
+/// `smpl` chunks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cues {
+    /// cue points in file order
+    pub points: Vec<CuePoint>,
+    pub loops: Vec<Loop>,
+    /// the rest of the `smpl` chunk; `None` if the file had none
+    pub sampler: Option<Sampler>,
+    /// `adtl` entries other than labels, notes and region lengths, kept
+    /// verbatim
+    pub adtl: Vec<Chunk>,
+    /// labels, notes and region lengths for ids with no `cue ` entry, which
+    /// are written back to `adtl` without adding a cue point
+    pub orphans: Vec<CuePoint>,
+}
+
+fn u32_at(bytes: &[u8], n: usize) -> u32 {
+    u32::from_le_bytes([bytes[n], bytes[n + 1], bytes[n + 2], bytes[n + 3]])
+}
+
+impl Cues {
+    /// The point with id `id` for an `adtl` entry to fill in. Ids without a
+    /// cue point yet go to `orphans`, since `adtl` may come before the `cue `
+    /// chunk it labels.
+    fn point_mut(&mut self, id: u32) -> &mut CuePoint {
+        if let Some(n) = self.points.iter().position(|point| point.id == id) {
+            return &mut self.points[n];
+        }
+        let n = match self.orphans.iter().position(|point| point.id == id) {
+            Some(n) => n,
+            None => {
+                self.orphans.push(CuePoint {
+                    id,
+                    ..CuePoint::default()
+                });
+                self.orphans.len() - 1
+            }
+        };
+        &mut self.orphans[n]
+    }
+
+    /// The point with id `id`, taking in its labels from `orphans` if they
+    /// came first.
+    fn cue_point_mut(&mut self, id: u32) -> &mut CuePoint {
+        let n = match self.points.iter().position(|point| point.id == id) {
+            Some(n) => n,
+            None => {
+                let point = match self.orphans.iter().position(|point| point.id == id) {
+                    Some(n) => self.orphans.remove(n),
+                    None => CuePoint {
+                        id,
+                        ..CuePoint::default()
+                    },
+                };
+                self.points.push(point);
+                self.points.len() - 1
+            }
+        };
+        &mut self.points[n]
+    }
+
+    /// Takes in `chunk` if it is a cue or loop chunk, returning it back
+    /// otherwise or if it cannot be parsed.
+    pub(super) fn read_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        let parsed = match chunk.id.as_str() {
+            "cue " => self.read_cue(&chunk.data),
+            "LIST" if chunk.data.starts_with(b"adtl") => self.read_adtl(&chunk.data[4..]),
+            "smpl" => self.read_smpl(&chunk.data),
+            _ => false,
+        };
+        if parsed {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+
+    fn read_cue(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() < 4 {
+            return false;
+        }
+        let count = u32_at(bytes, 0) as usize;
+        if bytes.len() < 4 + 24 * count {
+            return false;
+        }
+        for n in 0..count {
+            let entry = &bytes[4 + 24 * n..4 + 24 * (n + 1)];
+            // the sample offset into the data chunk; the other fields only
+            // matter for files with a wavl or playlist
+            self.cue_point_mut(u32_at(entry, 0)).position = u32_at(entry, 20);
+        }
+        true
+    }
+
+    fn read_adtl(&mut self, bytes: &[u8]) -> bool {
+        for chunk in sub_chunks(bytes) {
+            if chunk.data.len() < 4 {
+                continue;
+            }
+            let id = u32_at(&chunk.data, 0);
+            match chunk.id.as_str() {
+                "labl" => self.point_mut(id).label = Some(zstr(&chunk.data[4..])),
+                "note" => self.point_mut(id).note = Some(zstr(&chunk.data[4..])),
+                "ltxt" if chunk.data.len() >= 8 => {
+                    self.point_mut(id).length = u32_at(&chunk.data, 4);
+                }
+                _ => self.adtl.push(chunk),
+            }
+        }
+        true
+    }
+
+    fn read_smpl(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() < 36 {
+            return false;
+        }
+        let nloops = u32_at(bytes, 28) as usize;
+        let data_size = u32_at(bytes, 32) as usize;
+        let loops_end = 36 + 24 * nloops;
+        if bytes.len() < loops_end {
+            return false;
+        }
+        self.loops = (0..nloops)
+            .map(|n| {
+                let entry = &bytes[36 + 24 * n..];
+                Loop {
+                    id: u32_at(entry, 0),
+                    kind: LoopKind::new(u32_at(entry, 4)),
+                    start: u32_at(entry, 8),
+                    end: u32_at(entry, 12),
+                    fraction: u32_at(entry, 16),
+                    play_count: u32_at(entry, 20),
+                }
+            })
+            .collect();
+        let data_end = (loops_end + data_size).min(bytes.len());
+        self.sampler = Some(Sampler {
+            manufacturer: u32_at(bytes, 0),
+            product: u32_at(bytes, 4),
+            sample_period: u32_at(bytes, 8),
+            midi_unity_note: u32_at(bytes, 12),
+            midi_pitch_fraction: u32_at(bytes, 16),
+            smpte_format: u32_at(bytes, 20),
+            smpte_offset: u32_at(bytes, 24),
+            data: bytes[loops_end..data_end].to_vec(),
+        });
+        true
+    }
+
+    /// The chunks holding these cues. A file with loops but no sampler
+    /// header gets a default one for `sample_rate`.
+    pub(super) fn to_chunks(&self, sample_rate: u32) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        if !self.points.is_empty() {
+            let mut data = (self.points.len() as u32).to_le_bytes().to_vec();
+            for point in &self.points {
+                data.extend_from_slice(&point.id.to_le_bytes());
+                data.extend_from_slice(&point.position.to_le_bytes());
+                data.extend_from_slice(b"data");
+                data.extend_from_slice(&[0; 8]);
+                data.extend_from_slice(&point.position.to_le_bytes());
+            }
+            chunks.push(Chunk {
+                id: "cue ".to_string(),
+                data,
+            });
+        }
+
+        let mut adtl = b"adtl".to_vec();
+        for point in self.points.iter().chain(&self.orphans) {
+            let id = point.id.to_le_bytes();
+            for (kind, text) in &[("labl", &point.label), ("note", &point.note)] {
+                if let Some(text) = text {
+                    let mut entry = id.to_vec();
+                    entry.extend_from_slice(text.as_bytes());
+                    entry.push(0);
+                    put_sub_chunk(&mut adtl, kind, &entry);
+                }
+            }
+            if point.length > 0 {
+                let mut entry = id.to_vec();
+                entry.extend_from_slice(&point.length.to_le_bytes());
+                entry.extend_from_slice(b"rgn ");
+                // country, language, dialect and code page
+                entry.extend_from_slice(&[0; 8]);
+                put_sub_chunk(&mut adtl, "ltxt", &entry);
+            }
+        }
+        for chunk in &self.adtl {
+            put_sub_chunk(&mut adtl, &chunk.id, &chunk.data);
+        }
+        if adtl.len() > 4 {
+            chunks.push(Chunk {
+                id: "LIST".to_string(),
+                data: adtl,
+            });
+        }
+
+        if self.sampler.is_some() || !self.loops.is_empty() {
+            let sampler = self
+                .sampler
+                .clone()
+                .unwrap_or_else(|| Sampler::new(sample_rate));
+            let mut data = Vec::new();
+            for field in &[
+                sampler.manufacturer,
+                sampler.product,
+                sampler.sample_period,
+                sampler.midi_unity_note,
+                sampler.midi_pitch_fraction,
+                sampler.smpte_format,
+                sampler.smpte_offset,
+                self.loops.len() as u32,
+                sampler.data.len() as u32,
+            ] {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+            for l in &self.loops {
+                for field in &[
+                    l.id,
+                    l.kind.to_u32(),
+                    l.start,
+                    l.end,
+                    l.fraction,
+                    l.play_count,
+                ] {
+                    data.extend_from_slice(&field.to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&sampler.data);
+            chunks.push(Chunk {
+                id: "smpl".to_string(),
+                data,
+            });
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{CuePoint, Cues, Loop, LoopKind};
+    use crate::io::sample::Samples;
+    use crate::io::wav::{Chunk, WavReader, WAV};
+    use std::io::Cursor;
+
+    fn cues() -> Cues {
+        Cues {
+            points: vec![
+                CuePoint {
+                    id: 1,
+                    position: 2,
+                    label: Some("drop".to_string()),
+                    ..CuePoint::default()
+                },
+                CuePoint {
+                    id: 2,
+                    position: 4,
+                    length: 3,
+                    note: Some("4 beat loop".to_string()),
+                    ..CuePoint::default()
+                },
+            ],
+            loops: vec![Loop {
+                id: 2,
+                kind: LoopKind::Forward,
+                start: 4,
+                end: 6,
+                fraction: 0,
+                play_count: 0,
+            }],
+            sampler: None,
+            adtl: vec![Chunk {
+                id: "file".to_string(),
+                data: vec![3, 0, 0, 0, 1],
+            }],
+            orphans: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cues_survive_write() {
+        let mut wav = WAV::new(44100, 1, Samples::I16(vec![0; 8])).unwrap();
+        wav.cues = cues();
+        let mut bytes = Vec::new();
+        wav.write_to(&mut bytes).unwrap();
+
+        let wav = WAV::from_reader(Cursor::new(&bytes)).unwrap();
+        let sampler = wav.cues.sampler.as_ref().unwrap();
+        assert_eq!(sampler.sample_period, 22675);
+        assert_eq!(
+            wav.cues,
+            Cues {
+                sampler: Some(sampler.clone()),
+                ..cues()
+            }
+        );
+        assert!(wav.trailing_chunks.is_empty());
+        assert_eq!(wav.validate(), vec![]);
+
+        // the streaming reader finds cues after the data chunk too
+        let reader = WavReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.cues, wav.cues);
+    }
+
+    #[test]
+    fn labels_before_cue_chunk() {
+        let mut wav = WAV::new(44100, 1, Samples::I16(vec![0; 8])).unwrap();
+        let mut chunks = cues().to_chunks(44100);
+        // LIST/adtl first, then cue
+        chunks.swap(0, 1);
+        wav.chunks = chunks;
+        let mut bytes = Vec::new();
+        wav.write_to(&mut bytes).unwrap();
+
+        let wav = WAV::from_reader(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.cues.points, cues().points);
+    }
+
+    #[test]
+    fn orphan_labels_add_no_cue_points() {
+        let mut wav = WAV::new(44100, 1, Samples::I16(vec![0; 8])).unwrap();
+        let mut input = cues();
+        input.orphans.push(CuePoint {
+            id: 9,
+            label: Some("lost".to_string()),
+            ..CuePoint::default()
+        });
+        wav.cues = input.clone();
+        let mut bytes = Vec::new();
+        wav.write_to(&mut bytes).unwrap();
+
+        let wav = WAV::from_reader(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.cues.points, input.points);
+        assert_eq!(wav.cues.orphans, input.orphans);
+    }
+}
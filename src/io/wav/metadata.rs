@@ -0,0 +1,378 @@
+use super::{put_sub_chunk, sub_chunks, zstr, Chunk};
+use crate::io::id3;
+
+/// The tags a DJ sorts and searches a crate by, gathered from a file's
+/// `LIST/INFO`, `id3 ` and `bext` chunks. Where a field is in both INFO and
+/// ID3, the ID3 value wins. Everything else in those chunks is kept in `info`,
+/// `id3` and `bext` so writing the file back loses nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub comment: Option<String>,
+    pub bpm: Option<f64>,
+    pub key: Option<String>,
+    /// INFO entries without a field above, such as `ISFT`, in file order
+    pub info: Vec<(String, String)>,
+    /// the ID3 frames without a field above; `None` if the file had no
+    /// `id3 ` chunk
+    pub id3: Option<id3::Tag>,
+    pub bext: Option<Bext>,
+}
+
+/// INFO ids of the fields above, in the order they are written.
+const INFO_FIELDS: [&str; 6] = ["INAM", "IART", "IPRD", "IGNR", "ICRD", "ICMT"];
+/// ID3 text frames holding the fields above, other than date, BPM and comment
+/// which need special handling.
+const ID3_FIELDS: [&str; 5] = ["TIT2", "TPE1", "TALB", "TCON", "TKEY"];
+
+impl TrackMetadata {
+    /// The field stored under INFO id or ID3 frame id `id`.
+    fn field(&self, id: &str) -> Option<&Option<String>> {
+        match id {
+            "INAM" | "TIT2" => Some(&self.title),
+            "IART" | "TPE1" => Some(&self.artist),
+            "IPRD" | "TALB" => Some(&self.album),
+            "IGNR" | "TCON" => Some(&self.genre),
+            "ICRD" => Some(&self.date),
+            "ICMT" => Some(&self.comment),
+            "TKEY" => Some(&self.key),
+            _ => None,
+        }
+    }
+
+    fn field_mut(&mut self, id: &str) -> Option<&mut Option<String>> {
+        match id {
+            "INAM" | "TIT2" => Some(&mut self.title),
+            "IART" | "TPE1" => Some(&mut self.artist),
+            "IPRD" | "TALB" => Some(&mut self.album),
+            "IGNR" | "TCON" => Some(&mut self.genre),
+            "ICRD" => Some(&mut self.date),
+            "ICMT" => Some(&mut self.comment),
+            "TKEY" => Some(&mut self.key),
+            _ => None,
+        }
+    }
+
+    /// Takes in `chunk` if it is one of the metadata chunks, returning it
+    /// back otherwise. A metadata chunk that cannot be parsed is returned
+    /// too, so it is kept verbatim rather than making the audio unreadable.
+    pub(super) fn read_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        match chunk.id.as_str() {
+            "LIST" if chunk.data.starts_with(b"INFO") => self.read_info(&chunk.data[4..]),
+            "id3 " | "ID3 " => match id3::Tag::parse(&chunk.data) {
+                Some(tag) => self.read_id3(tag),
+                None => return Some(chunk),
+            },
+            "bext" => match Bext::parse(&chunk.data) {
+                Some(bext) => self.bext = Some(bext),
+                None => return Some(chunk),
+            },
+            _ => return Some(chunk),
+        }
+        None
+    }
+
+    fn read_info(&mut self, bytes: &[u8]) {
+        for Chunk { id, data } in sub_chunks(bytes) {
+            let value = zstr(&data);
+            match self.field_mut(&id) {
+                // keep a value already read from ID3
+                Some(field) => {
+                    field.get_or_insert(value);
+                }
+                None => self.info.push((id, value)),
+            }
+        }
+    }
+
+    /// Takes the fields this type holds out of `tag`, keeping the rest of it
+    /// in `id3`.
+    pub(crate) fn read_id3(&mut self, mut tag: id3::Tag) {
+        for &id in &ID3_FIELDS {
+            if let Some(text) = tag.text(id) {
+                *self.field_mut(id).unwrap() = Some(text);
+                tag.set_text(id, None);
+            }
+        }
+        if let Some(bpm) = tag.text("TBPM").and_then(|bpm| bpm.trim().parse().ok()) {
+            self.bpm = Some(bpm);
+            tag.set_text("TBPM", None);
+        }
+        for &id in &["TDRC", "TYER"] {
+            if let Some(date) = tag.text(id) {
+                self.date = Some(date);
+                tag.set_text(id, None);
+            }
+        }
+        if let Some(comment) = tag.comment() {
+            self.comment = Some(comment);
+            tag.set_comment(None);
+        }
+        self.id3 = Some(tag);
+    }
+
+    /// The ID3 tag to write: `id3` with the fields above filled in. BPM and
+    /// key are only written to ID3, so there is a tag if either is set even
+    /// when the file had none.
+    pub(crate) fn id3_tag(&self) -> Option<id3::Tag> {
+        if self.id3.is_none() && self.bpm.is_none() && self.key.is_none() {
+            return None;
+        }
+        let mut tag = self.id3.clone().unwrap_or_else(|| id3::Tag::new(3));
+        for &id in &ID3_FIELDS {
+            tag.set_text(id, self.field(id).unwrap().as_deref());
+        }
+        let date = if tag.version >= 4 { "TDRC" } else { "TYER" };
+        tag.set_text(date, self.date.as_deref());
+        // TBPM holds a whole number of beats per minute
+        let bpm = self.bpm.map(|bpm| (bpm.round() as u32).to_string());
+        tag.set_text("TBPM", bpm.as_deref());
+        tag.set_comment(self.comment.as_deref());
+        Some(tag)
+    }
+
+    /// The chunks holding this metadata: those written before the data chunk
+    /// and those written after it. ID3 tags go at the end of the file where
+    /// most tools look for them.
+    pub(super) fn to_chunks(&self) -> (Vec<Chunk>, Vec<Chunk>) {
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        if let Some(bext) = &self.bext {
+            before.push(bext.to_chunk());
+        }
+
+        let mut entries: Vec<(String, String)> = INFO_FIELDS
+            .iter()
+            .filter_map(|&id| {
+                let value = self.field(id)?.clone()?;
+                Some((id.to_string(), value))
+            })
+            .collect();
+        entries.extend(self.info.iter().cloned());
+        if !entries.is_empty() {
+            let mut data = b"INFO".to_vec();
+            for (id, value) in entries {
+                let mut value = value.into_bytes();
+                value.push(0);
+                put_sub_chunk(&mut data, &id, &value);
+            }
+            before.push(Chunk {
+                id: "LIST".to_string(),
+                data,
+            });
+        }
+
+        if let Some(tag) = self.id3_tag() {
+            after.push(Chunk {
+                id: "id3 ".to_string(),
+                data: tag.to_bytes(),
+            });
+        }
+        (before, after)
+    }
+}
+
+/// Writes `s` into a null padded field of `size` bytes, cutting it short
+/// at the last whole character that fits.
+fn put_str(data: &mut Vec<u8>, s: &str, size: usize) {
+    let mut end = s.len().min(size);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut field = s.as_bytes()[..end].to_vec();
+    field.resize(size, 0);
+    data.extend_from_slice(&field);
+}
+
+/// The Broadcast Wave Format `bext` chunk (EBU Tech 3285) describing where a
+/// recording came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bext {
+    pub description: String,
+    pub originator: String,
+    pub originator_reference: String,
+    /// yyyy-mm-dd
+    pub origination_date: String,
+    /// hh:mm:ss
+    pub origination_time: String,
+    /// the first sample's offset, in samples since midnight
+    pub time_reference: u64,
+    pub version: u16,
+    pub umid: [u8; 64],
+    pub loudness_value: i16,
+    pub loudness_range: i16,
+    pub max_true_peak_level: i16,
+    pub max_momentary_loudness: i16,
+    pub max_short_term_loudness: i16,
+    pub coding_history: String,
+}
+
+impl Bext {
+    /// The size of every field before the coding history, including 180
+    /// reserved bytes.
+    const SIZE: usize = 602;
+
+    /// Parses a `bext` payload, or returns `None` if it is too short.
+    fn parse(bytes: &[u8]) -> Option<Bext> {
+        if bytes.len() < Bext::SIZE {
+            return None;
+        }
+        let i16_at = |n: usize| i16::from_le_bytes([bytes[n], bytes[n + 1]]);
+        let mut time_reference = [0u8; 8];
+        time_reference.copy_from_slice(&bytes[338..346]);
+        let mut umid = [0u8; 64];
+        umid.copy_from_slice(&bytes[348..412]);
+        Some(Bext {
+            description: zstr(&bytes[0..256]),
+            originator: zstr(&bytes[256..288]),
+            originator_reference: zstr(&bytes[288..320]),
+            origination_date: zstr(&bytes[320..330]),
+            origination_time: zstr(&bytes[330..338]),
+            time_reference: u64::from_le_bytes(time_reference),
+            version: u16::from_le_bytes([bytes[346], bytes[347]]),
+            umid,
+            loudness_value: i16_at(412),
+            loudness_range: i16_at(414),
+            max_true_peak_level: i16_at(416),
+            max_momentary_loudness: i16_at(418),
+            max_short_term_loudness: i16_at(420),
+            coding_history: zstr(&bytes[Bext::SIZE..]),
+        })
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(Bext::SIZE + self.coding_history.len());
+        put_str(&mut data, &self.description, 256);
+        put_str(&mut data, &self.originator, 32);
+        put_str(&mut data, &self.originator_reference, 32);
+        put_str(&mut data, &self.origination_date, 10);
+        put_str(&mut data, &self.origination_time, 8);
+        data.extend_from_slice(&self.time_reference.to_le_bytes());
+        data.extend_from_slice(&self.version.to_le_bytes());
+        data.extend_from_slice(&self.umid);
+        for loudness in &[
+            self.loudness_value,
+            self.loudness_range,
+            self.max_true_peak_level,
+            self.max_momentary_loudness,
+            self.max_short_term_loudness,
+        ] {
+            data.extend_from_slice(&loudness.to_le_bytes());
+        }
+        data.resize(Bext::SIZE, 0);
+        data.extend_from_slice(self.coding_history.as_bytes());
+        Chunk {
+            id: "bext".to_string(),
+            data,
+        }
+    }
+}
+
+impl Default for Bext {
+    fn default() -> Bext {
+        Bext {
+            description: String::new(),
+            originator: String::new(),
+            originator_reference: String::new(),
+            origination_date: String::new(),
+            origination_time: String::new(),
+            time_reference: 0,
+            version: 2,
+            umid: [0; 64],
+            loudness_value: 0,
+            loudness_range: 0,
+            max_true_peak_level: 0,
+            max_momentary_loudness: 0,
+            max_short_term_loudness: 0,
+            coding_history: String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{Bext, TrackMetadata};
+    use crate::io::id3;
+    use crate::io::sample::Samples;
+    use crate::io::wav::{Chunk, WAV};
+    use std::io::Cursor;
+
+    fn round_trip(wav: WAV) -> WAV {
+        let mut bytes = Vec::new();
+        wav.write_to(&mut bytes).unwrap();
+        WAV::from_reader(Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn tags_survive_write() {
+        let mut wav = WAV::new(44100, 1, Samples::I16(vec![1, 2, 3])).unwrap();
+        wav.metadata = TrackMetadata {
+            title: Some("Xtal".to_string()),
+            artist: Some("Aphex Twin".to_string()),
+            genre: Some("Ambient".to_string()),
+            comment: Some("intro only".to_string()),
+            bpm: Some(123.5),
+            key: Some("Bbm".to_string()),
+            info: vec![("ISFT".to_string(), "cldj".to_string())],
+            bext: Some(Bext {
+                description: "side A".to_string(),
+                time_reference: 44100 * 60,
+                coding_history: "A=PCM,F=44100,W=16,M=mono\r\n".to_string(),
+                ..Bext::default()
+            }),
+            ..TrackMetadata::default()
+        };
+        let expected = TrackMetadata {
+            id3: Some(id3::Tag::new(3)),
+            bpm: Some(124.0),
+            ..wav.metadata.clone()
+        };
+
+        let wav = round_trip(wav);
+        assert_eq!(wav.metadata, expected);
+        assert!(wav.chunks.is_empty());
+        assert!(wav.trailing_chunks.is_empty());
+        assert_eq!(wav.validate(), vec![]);
+    }
+
+    #[test]
+    fn id3_wins_over_info() {
+        let mut tag = id3::Tag::new(4);
+        tag.set_text("TIT2", Some("Radio Edit"));
+        tag.set_text("TPUB", Some("Warp"));
+        let mut wav = WAV::new(44100, 1, Samples::I16(vec![0])).unwrap();
+        wav.chunks.push(Chunk {
+            id: "LIST".to_string(),
+            data: b"INFOINAM\x05\x00\x00\x00Xtal\x00\x00IART\x03\x00\x00\x00AFX\x00".to_vec(),
+        });
+        wav.trailing_chunks.push(Chunk {
+            id: "id3 ".to_string(),
+            data: tag.to_bytes(),
+        });
+
+        let wav = round_trip(wav);
+        assert_eq!(wav.metadata.title.as_deref(), Some("Radio Edit"));
+        assert_eq!(wav.metadata.artist.as_deref(), Some("AFX"));
+        // frames without a field are kept
+        let tag = wav.metadata.id3.as_ref().unwrap();
+        assert_eq!(tag.version, 4);
+        assert_eq!(tag.text("TPUB").as_deref(), Some("Warp"));
+        assert_eq!(tag.text("TIT2"), None);
+    }
+
+    #[test]
+    fn long_bext_fields_are_cut_between_characters() {
+        let mut wav = WAV::new(44100, 1, Samples::I16(vec![0])).unwrap();
+        wav.metadata.bext = Some(Bext {
+            originator: format!("{}é", "a".repeat(31)),
+            ..Bext::default()
+        });
+        let wav = round_trip(wav);
+        let bext = wav.metadata.bext.unwrap();
+        assert_eq!(bext.originator, "a".repeat(31));
+    }
+}
@@ -0,0 +1,1291 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::str;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::error::ContainerError;
+use super::sample::{ChannelSelection, SampleFormat, Samples};
+
+mod cue;
+mod metadata;
+mod reader;
+mod validate;
+mod writer;
+
+pub use cue::{CuePoint, Cues, Loop, LoopKind, Sampler};
+pub use metadata::{Bext, TrackMetadata};
+pub use reader::WavReader;
+pub use validate::{Field, Issue};
+pub use writer::WavWriter;
+
+/// Errors reading or writing WAV files.
+pub type WavError = ContainerError;
+
+#[derive(Debug)]
+pub struct RIFFHeader {
+    pub riff: String,
+    pub file_size: u32,
+    pub four_cc: String,
+}
+
+impl RIFFHeader {
+    fn new(bytes: &[u8; 12]) -> Result<RIFFHeader, WavError> {
+        let riff = match &bytes[0..4] {
+            b"RIFF" | b"RF64" | b"BW64" => fourcc(&bytes[0..4]),
+            found => {
+                return Err(WavError::BadMagic {
+                    offset: 0,
+                    expected: "RIFF",
+                    found: fourcc(found),
+                })
+            }
+        };
+        let file_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let four_cc = match &bytes[8..12] {
+            b"WAVE" => fourcc(&bytes[8..12]),
+            found => {
+                return Err(WavError::BadMagic {
+                    offset: 8,
+                    expected: "WAVE",
+                    found: fourcc(found),
+                })
+            }
+        };
+        let header = RIFFHeader {
+            riff,
+            file_size,
+            four_cc,
+        };
+        Ok(header)
+    }
+
+    fn write<W: Write>(self, writer: &mut W) -> Result<(), WavError> {
+        writer.write_all(self.riff.as_bytes())?;
+        writer.write_u32::<LittleEndian>(self.file_size)?;
+        writer.write_all(self.four_cc.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Reads the 12 byte RIFF header at the start of a file.
+fn read_riff_header<R: Read>(reader: &mut R) -> Result<RIFFHeader, WavError> {
+    let mut buf = [0u8; 12];
+    let found = read_full(reader, &mut buf)?;
+    if found < buf.len() {
+        return Err(WavError::Truncated {
+            offset: 0,
+            expected: buf.len() as u64,
+            found: found as u64,
+        });
+    }
+    RIFFHeader::new(&buf)
+}
+
+/// A chunk id as text, with anything that isn't ASCII replaced.
+fn fourcc(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// A null terminated or null padded string, as UTF-8 if it is valid and as
+/// Latin-1 otherwise.
+pub(crate) fn zstr(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let bytes = &bytes[..end];
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// The chunks packed inside a `LIST` chunk's payload, after its list type.
+/// A last chunk that runs past the end is cut short.
+fn sub_chunks(mut bytes: &[u8]) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    while bytes.len() >= 8 {
+        let size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let end = 8usize.saturating_add(size).min(bytes.len());
+        chunks.push(Chunk {
+            id: fourcc(&bytes[0..4]),
+            data: bytes[8..end].to_vec(),
+        });
+        bytes = &bytes[end.saturating_add(size % 2).min(bytes.len())..];
+    }
+    chunks
+}
+
+/// Appends a sub-chunk holding `data`, and its pad byte, to a `LIST` payload.
+fn put_sub_chunk(list: &mut Vec<u8>, id: &str, data: &[u8]) {
+    list.extend_from_slice(id.as_bytes());
+    list.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    list.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        list.push(0);
+    }
+}
+
+/// A RIFF sub-chunk: a four character id followed by its payload. The pad
+/// byte that follows an odd sized payload is not part of `data`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub id: String,
+    pub data: Vec<u8>,
+}
+
+impl Chunk {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), WavError> {
+        writer.write_all(self.id.as_bytes())?;
+        // chunks over 4 GiB have their real size in the ds64 table
+        let size = u32::try_from(self.data.len()).unwrap_or(u32::MAX);
+        writer.write_u32::<LittleEndian>(size)?;
+        writer.write_all(&self.data)?;
+        if self.data.len() % 2 == 1 {
+            writer.write_u8(0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads into `buf` until it is full or the reader is exhausted, returning the
+/// number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// The `ds64` chunk of an RF64/BW64 file, which holds the 64 bit sizes of the
+/// RIFF body, the data chunk and any other chunk whose 32 bit size field is
+/// `0xFFFFFFFF`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DS64 {
+    pub riff_size: u64,
+    pub data_size: u64,
+    pub sample_count: u64,
+    /// sizes of chunks other than `data` that exceed 4 GiB
+    pub table: Vec<(String, u64)>,
+}
+
+impl DS64 {
+    fn new(chunk: &Chunk, offset: u64) -> Result<DS64, WavError> {
+        let bytes = &chunk.data;
+        if bytes.len() < 28 {
+            return Err(WavError::Corrupt {
+                offset,
+                reason: format!("ds64 chunk is {} bytes, expected at least 28", bytes.len()),
+            });
+        }
+        let u64_at = |n: usize| {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(&bytes[n..n + 8]);
+            u64::from_le_bytes(buf)
+        };
+        let table_length =
+            u32::from_le_bytes([bytes[24], bytes[25], bytes[26], bytes[27]]) as usize;
+        if bytes.len() < 28 + 12 * table_length {
+            return Err(WavError::Corrupt {
+                offset,
+                reason: format!("ds64 table of {} entries does not fit", table_length),
+            });
+        }
+        let table = (0..table_length)
+            .map(|n| {
+                let entry = 28 + 12 * n;
+                let id = String::from_utf8_lossy(&bytes[entry..entry + 4]).to_string();
+                (id, u64_at(entry + 4))
+            })
+            .collect();
+        Ok(DS64 {
+            riff_size: u64_at(0),
+            data_size: u64_at(8),
+            sample_count: u64_at(16),
+            table,
+        })
+    }
+
+    fn size(&self) -> u32 {
+        28 + 12 * self.table.len() as u32
+    }
+
+    /// The real size of chunk `id` whose 32 bit size field reads `size`.
+    fn chunk_size(&self, id: &str, size: u32) -> u64 {
+        if size != u32::MAX {
+            return size as u64;
+        }
+        if id == "data" {
+            return self.data_size;
+        }
+        self.table
+            .iter()
+            .find(|(table_id, _)| table_id == id)
+            .map_or(size as u64, |(_, size)| *size)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), WavError> {
+        writer.write_all(b"ds64")?;
+        writer.write_u32::<LittleEndian>(self.size())?;
+        writer.write_u64::<LittleEndian>(self.riff_size)?;
+        writer.write_u64::<LittleEndian>(self.data_size)?;
+        writer.write_u64::<LittleEndian>(self.sample_count)?;
+        writer.write_u32::<LittleEndian>(self.table.len() as u32)?;
+        for (id, size) in &self.table {
+            writer.write_all(id.as_bytes())?;
+            writer.write_u64::<LittleEndian>(*size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the sub-chunks following a RIFF header, in file order, along
+/// with the byte offset each starts at. Once a `ds64` chunk has been seen its
+/// 64 bit sizes are used for later chunks.
+pub struct Chunks<R: Read> {
+    reader: R,
+    offset: u64,
+    ds64: Option<DS64>,
+    done: bool,
+    lenient: bool,
+    /// the declared size of a final chunk that ran past the end of the file,
+    /// only set when lenient
+    truncated: Option<u64>,
+}
+
+impl<R: Read> Chunks<R> {
+    /// Walks the chunks of `reader`, which must be positioned just after the
+    /// 12 byte RIFF header.
+    pub fn new(reader: R) -> Chunks<R> {
+        Chunks {
+            reader,
+            offset: 12,
+            ds64: None,
+            done: false,
+            lenient: false,
+            truncated: None,
+        }
+    }
+
+    /// Like `new`, but a file that ends partway through a chunk yields what
+    /// there is of that chunk as the last one instead of an error.
+    pub fn lenient(reader: R) -> Chunks<R> {
+        Chunks {
+            lenient: true,
+            ..Chunks::new(reader)
+        }
+    }
+
+    /// The size the last chunk's header declared, if the file ended before
+    /// all of it. Only lenient iterators yield truncated chunks.
+    pub fn truncated(&self) -> Option<u64> {
+        self.truncated
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<(u64, Chunk)>, WavError> {
+        let offset = self.offset;
+        let (id, size) = match read_chunk_header(&mut self.reader, offset) {
+            Ok(Some(header)) => header,
+            Ok(None) => return Ok(None),
+            // a few stray bytes after the last chunk
+            Err(WavError::Truncated { .. }) if self.lenient => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let size = match &self.ds64 {
+            Some(ds64) => ds64.chunk_size(&id, size),
+            None => size as u64,
+        };
+        let chunk = if self.lenient {
+            let chunk = read_partial_chunk_body(&mut self.reader, offset, id, size)?;
+            if (chunk.data.len() as u64) < size {
+                self.truncated = Some(size);
+                self.done = true;
+            }
+            chunk
+        } else {
+            read_chunk_body(&mut self.reader, offset, id, size)?
+        };
+        if chunk.id == "ds64" {
+            self.ds64 = Some(DS64::new(&chunk, offset)?);
+        }
+        self.offset += 8 + size + size % 2;
+        Ok(Some((offset, chunk)))
+    }
+}
+
+/// Reads the id and declared size of the chunk at `offset`, or `None` at a
+/// clean end of file.
+fn read_chunk_header<R: Read>(
+    reader: &mut R,
+    offset: u64,
+) -> Result<Option<(String, u32)>, WavError> {
+    let mut header = [0u8; 8];
+    match read_full(reader, &mut header)? {
+        0 => return Ok(None),
+        8 => (),
+        found => {
+            return Err(WavError::Truncated {
+                offset,
+                expected: 8,
+                found: found as u64,
+            })
+        }
+    }
+    let id = fourcc(&header[0..4]);
+    let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    Ok(Some((id, size)))
+}
+
+/// Reads the payload of the chunk at `offset` whose header has just been
+/// read, consuming the pad byte if there is one.
+fn read_chunk_body<R: Read>(
+    reader: &mut R,
+    offset: u64,
+    id: String,
+    size: u64,
+) -> Result<Chunk, WavError> {
+    let chunk = read_partial_chunk_body(reader, offset, id, size)?;
+    let found = chunk.data.len() as u64;
+    if found < size {
+        return Err(WavError::Truncated {
+            offset,
+            expected: 8 + size,
+            found: 8 + found,
+        });
+    }
+    Ok(chunk)
+}
+
+/// Like `read_chunk_body`, but returns as much of the payload as the file
+/// holds when it ends early.
+fn read_partial_chunk_body<R: Read>(
+    reader: &mut R,
+    offset: u64,
+    id: String,
+    size: u64,
+) -> Result<Chunk, WavError> {
+    let size = usize::try_from(size).map_err(|_| WavError::Corrupt {
+        offset,
+        reason: format!("chunk {:?} of {} bytes does not fit in memory", id, size),
+    })?;
+    // grow the buffer as data arrives; a broken header can claim gigabytes
+    let mut data = Vec::new();
+    let found = reader.by_ref().take(size as u64).read_to_end(&mut data)?;
+    if found == size && size % 2 == 1 {
+        // a missing pad byte at the very end of the file is harmless
+        read_full(reader, &mut [0u8; 1])?;
+    }
+    Ok(Chunk { id, data })
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = Result<(u64, Chunk), WavError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_chunk() {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Every `KSDATAFORMAT_SUBTYPE_*` GUID that wraps a plain format tag stores
+/// the tag in its first two bytes followed by these 14.
+const SUBTYPE_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// The `WAVE_FORMAT_EXTENSIBLE` block that follows `cbSize` in a 40 byte fmt
+/// chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extensible {
+    pub valid_bits_per_sample: u16,
+    /// bit field of `SPEAKER_*` positions, one bit per channel in order
+    pub channel_mask: u32,
+    pub sub_format: [u8; 16],
+}
+
+impl Extensible {
+    const SIZE: usize = 22;
+
+    fn new(bytes: &[u8], offset: u64) -> Result<Extensible, WavError> {
+        if bytes.len() < Extensible::SIZE {
+            return Err(WavError::Corrupt {
+                offset,
+                reason: format!(
+                    "extensible fmt block is {} bytes, expected {}",
+                    bytes.len(),
+                    Extensible::SIZE
+                ),
+            });
+        }
+        let mut sub_format = [0u8; 16];
+        sub_format.copy_from_slice(&bytes[6..22]);
+        Ok(Extensible {
+            valid_bits_per_sample: u16::from_le_bytes([bytes[0], bytes[1]]),
+            channel_mask: u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+            sub_format,
+        })
+    }
+
+    /// An extensible block whose sub-format wraps the plain format tag `format`.
+    pub fn from_format_tag(
+        format: u16,
+        valid_bits_per_sample: u16,
+        channel_mask: u32,
+    ) -> Extensible {
+        let mut sub_format = [0u8; 16];
+        sub_format[..2].copy_from_slice(&format.to_le_bytes());
+        sub_format[2..].copy_from_slice(&SUBTYPE_GUID_TAIL);
+        Extensible {
+            valid_bits_per_sample,
+            channel_mask,
+            sub_format,
+        }
+    }
+
+    /// The plain format tag wrapped by `sub_format`, if it is one of the
+    /// standard `KSDATAFORMAT_SUBTYPE_*` GUIDs.
+    pub fn format_tag(&self) -> Option<u16> {
+        if self.sub_format[2..] == SUBTYPE_GUID_TAIL {
+            Some(u16::from_le_bytes([self.sub_format[0], self.sub_format[1]]))
+        } else {
+            None
+        }
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), WavError> {
+        writer.write_u16::<LittleEndian>(self.valid_bits_per_sample)?;
+        writer.write_u32::<LittleEndian>(self.channel_mask)?;
+        writer.write_all(&self.sub_format)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FMTHeader {
+    pub fmt: String,
+    pub header_size: u32,
+    pub format: u16,
+    pub nchannels: u16,
+    pub sample_rate: u32,
+    pub byte_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    /// present when `format` is `WAVE_FORMAT_EXTENSIBLE`
+    pub extensible: Option<Extensible>,
+    /// bytes following the 16 byte PCM fields that cldj does not interpret;
+    /// for extensible headers these are whatever follows the extensible block
+    pub extension: Vec<u8>,
+}
+
+impl FMTHeader {
+    fn new(chunk: &Chunk, offset: u64) -> Result<FMTHeader, WavError> {
+        if chunk.id != "fmt " {
+            return Err(WavError::BadMagic {
+                offset,
+                expected: "fmt ",
+                found: chunk.id.clone(),
+            });
+        }
+        let bytes = &chunk.data;
+        if bytes.len() < 16 {
+            return Err(WavError::Corrupt {
+                offset,
+                reason: format!("fmt chunk is {} bytes, expected at least 16", bytes.len()),
+            });
+        }
+        let format = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let nchannels = u16::from_le_bytes([bytes[2], bytes[3]]);
+        let sample_rate = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let byte_rate = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        let block_align = u16::from_le_bytes([bytes[12], bytes[13]]);
+        let bits_per_sample = u16::from_le_bytes([bytes[14], bytes[15]]);
+        let mut extensible = None;
+        let mut extension = bytes[16..].to_vec();
+        if format == WAVE_FORMAT_EXTENSIBLE {
+            if bytes.len() < 18 {
+                return Err(WavError::Corrupt {
+                    offset,
+                    reason: "extensible fmt chunk is missing cbSize".to_string(),
+                });
+            }
+            let cb_size = u16::from_le_bytes([bytes[16], bytes[17]]) as usize;
+            if cb_size < Extensible::SIZE || bytes.len() < 18 + cb_size {
+                return Err(WavError::Corrupt {
+                    offset,
+                    reason: format!("extensible fmt chunk has invalid cbSize {}", cb_size),
+                });
+            }
+            extensible = Some(Extensible::new(&bytes[18..18 + cb_size], offset)?);
+            // anything past cbSize is padding, not part of the extension
+            extension = bytes[18 + Extensible::SIZE..18 + cb_size].to_vec();
+        }
+        let header = FMTHeader {
+            fmt: chunk.id.clone(),
+            header_size: bytes.len() as u32,
+            format,
+            nchannels,
+            sample_rate,
+            byte_rate,
+            block_align,
+            bits_per_sample,
+            extensible,
+            extension,
+        };
+        Ok(header)
+    }
+
+    /// The format tag the samples are encoded with, looking through
+    /// `WAVE_FORMAT_EXTENSIBLE` to its sub-format.
+    pub fn format_tag(&self) -> u16 {
+        match &self.extensible {
+            Some(extensible) if self.format == WAVE_FORMAT_EXTENSIBLE => {
+                extensible.format_tag().unwrap_or(WAVE_FORMAT_EXTENSIBLE)
+            }
+            _ => self.format,
+        }
+    }
+
+    /// The sample encoding described by `format` and `bits_per_sample`, if
+    /// cldj can decode it.
+    pub fn sample_format(&self) -> Option<SampleFormat> {
+        match (self.format_tag(), self.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Some(SampleFormat::U8),
+            (WAVE_FORMAT_PCM, 16) => Some(SampleFormat::I16),
+            (WAVE_FORMAT_PCM, 24) => Some(SampleFormat::I24),
+            (WAVE_FORMAT_PCM, 32) => Some(SampleFormat::I32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Some(SampleFormat::F32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Some(SampleFormat::F64),
+            _ => None,
+        }
+    }
+
+    /// Checks the fields every decoder relies on, returning the sample format.
+    /// `offset` is where the fmt chunk starts, for error reporting.
+    fn decodable(&self, offset: u64) -> Result<SampleFormat, WavError> {
+        let format = self.sample_format().ok_or_else(|| WavError::Unsupported {
+            offset,
+            reason: format!(
+                "format tag {:#06x} with {} bits per sample",
+                self.format_tag(),
+                self.bits_per_sample
+            ),
+        })?;
+        if self.nchannels == 0 {
+            return Err(WavError::Corrupt {
+                offset,
+                reason: "fmt chunk declares zero channels".to_string(),
+            });
+        }
+        Ok(format)
+    }
+
+    /// The size of the chunk payload these fields serialize to.
+    fn size(&self) -> u32 {
+        let extensible = match self.extensible {
+            Some(_) => 2 + Extensible::SIZE,
+            None => 0,
+        };
+        (16 + extensible + self.extension.len()) as u32
+    }
+
+    /// Rewrites every field derived from the sample layout to match `spec`,
+    /// leaving the header untouched if `spec` does not fit in them.
+    fn update(&mut self, spec: &WavSpec) -> Result<(), WavError> {
+        let byte_rate = spec.byte_rate()?;
+        let block_align = spec.block_align()?;
+        let tag = spec.format_tag();
+        match &mut self.extensible {
+            Some(extensible) => {
+                self.format = WAVE_FORMAT_EXTENSIBLE;
+                let bits = spec.format.bits_per_sample();
+                // keep e.g. 20 valid bits in a 24 bit container
+                let valid_bits = match extensible.valid_bits_per_sample {
+                    valid if valid > 0 && valid <= bits => valid,
+                    _ => bits,
+                };
+                *extensible = Extensible::from_format_tag(tag, valid_bits, extensible.channel_mask);
+            }
+            None => self.format = tag,
+        }
+        self.nchannels = spec.nchannels;
+        self.sample_rate = spec.sample_rate;
+        self.byte_rate = byte_rate;
+        self.block_align = block_align;
+        self.bits_per_sample = spec.format.bits_per_sample();
+        self.header_size = self.size();
+        Ok(())
+    }
+
+    fn write<W: Write>(self, writer: &mut W) -> Result<(), WavError> {
+        let size = self.size();
+        writer.write_all(self.fmt.as_bytes())?;
+        writer.write_u32::<LittleEndian>(size)?;
+        writer.write_u16::<LittleEndian>(self.format)?;
+        writer.write_u16::<LittleEndian>(self.nchannels)?;
+        writer.write_u32::<LittleEndian>(self.sample_rate)?;
+        writer.write_u32::<LittleEndian>(self.byte_rate)?;
+        writer.write_u16::<LittleEndian>(self.block_align)?;
+        writer.write_u16::<LittleEndian>(self.bits_per_sample)?;
+        if let Some(extensible) = &self.extensible {
+            let cb_size = Extensible::SIZE + self.extension.len();
+            writer.write_u16::<LittleEndian>(cb_size as u16)?;
+            extensible.write(writer)?;
+        }
+        writer.write_all(&self.extension)?;
+        if size % 2 == 1 {
+            writer.write_u8(0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The layout of the audio in a WAV file, from which every derived fmt field
+/// follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub sample_rate: u32,
+    pub nchannels: u16,
+    pub format: SampleFormat,
+}
+
+impl WavSpec {
+    pub fn format_tag(&self) -> u16 {
+        if self.format.is_float() {
+            WAVE_FORMAT_IEEE_FLOAT
+        } else {
+            WAVE_FORMAT_PCM
+        }
+    }
+
+    /// The bytes in one frame, or an error if there are too many channels
+    /// for the 16 bit field.
+    pub fn block_align(&self) -> Result<u16, WavError> {
+        let block_align = self.nchannels as u64 * self.format.bytes_per_sample() as u64;
+        u16::try_from(block_align).map_err(|_| {
+            WavError::InvalidInput(format!(
+                "a {} byte frame does not fit the block align field",
+                block_align
+            ))
+        })
+    }
+
+    /// The bytes in one second, or an error if that overflows the 32 bit
+    /// field.
+    pub fn byte_rate(&self) -> Result<u32, WavError> {
+        let byte_rate = self.sample_rate as u64 * self.block_align()? as u64;
+        u32::try_from(byte_rate).map_err(|_| {
+            WavError::InvalidInput(format!(
+                "{} bytes per second do not fit the byte rate field",
+                byte_rate
+            ))
+        })
+    }
+
+    /// A fmt header describing this layout. More than two channels need the
+    /// extensible form to carry a channel mask.
+    pub fn fmt_header(&self) -> Result<FMTHeader, WavError> {
+        let extensible = if self.nchannels > 2 {
+            let channel_mask = 1u32
+                .checked_shl(self.nchannels as u32)
+                .unwrap_or(0)
+                .wrapping_sub(1);
+            Some(Extensible::from_format_tag(
+                self.format_tag(),
+                self.format.bits_per_sample(),
+                channel_mask,
+            ))
+        } else {
+            None
+        };
+        let mut header = FMTHeader {
+            fmt: "fmt ".to_string(),
+            header_size: 0,
+            format: 0,
+            nchannels: 0,
+            sample_rate: 0,
+            byte_rate: 0,
+            block_align: 0,
+            bits_per_sample: 0,
+            extensible,
+            extension: Vec::new(),
+        };
+        header.update(self)?;
+        Ok(header)
+    }
+}
+
+#[derive(Debug)]
+pub struct DataHeader {
+    pub data: String,
+    pub size: u32,
+}
+
+impl DataHeader {
+    fn new(chunk: &Chunk, offset: u64) -> Result<DataHeader, WavError> {
+        if chunk.id != "data" {
+            return Err(WavError::BadMagic {
+                offset,
+                expected: "data",
+                found: chunk.id.clone(),
+            });
+        }
+        let header = DataHeader {
+            data: chunk.id.clone(),
+            size: u32::try_from(chunk.data.len()).unwrap_or(u32::MAX),
+        };
+        Ok(header)
+    }
+
+    fn write<W: Write>(self, writer: &mut W) -> Result<(), WavError> {
+        writer.write_all(self.data.as_bytes())?;
+        writer.write_u32::<LittleEndian>(self.size)?;
+        Ok(())
+    }
+}
+
+pub struct WAV {
+    pub riff_header: RIFFHeader,
+    /// present in RF64/BW64 files, which are written whenever the RIFF size
+    /// would not fit in 32 bits
+    pub ds64: Option<DS64>,
+    pub fmt_header: FMTHeader,
+    pub data_header: DataHeader,
+    pub signal: Samples,
+    /// chunks cldj does not interpret which appeared before the data chunk
+    pub chunks: Vec<Chunk>,
+    /// chunks cldj does not interpret which appeared after the data chunk
+    pub trailing_chunks: Vec<Chunk>,
+    pub metadata: TrackMetadata,
+    pub cues: Cues,
+    /// the next frame read through `AudioSource`
+    pub(crate) cursor: u64,
+}
+
+impl WAV {
+    pub fn from_file(filename: &str) -> Result<WAV, WavError> {
+        let f = File::open(filename)?;
+        WAV::from_reader(BufReader::new(f))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<WAV, WavError> {
+        WAV::load(reader, false)
+    }
+
+    /// Reads a file whose data chunk, or whatever chunk comes last, runs past
+    /// the end of the file, keeping the frames that are there. The declared
+    /// sizes are left as found so `validate` can report them.
+    pub fn from_file_lenient(filename: &str) -> Result<WAV, WavError> {
+        let f = File::open(filename)?;
+        WAV::from_reader_lenient(BufReader::new(f))
+    }
+
+    pub fn from_reader_lenient<R: Read>(reader: R) -> Result<WAV, WavError> {
+        WAV::load(reader, true)
+    }
+
+    fn load<R: Read>(mut reader: R, lenient: bool) -> Result<WAV, WavError> {
+        let riff_header = read_riff_header(&mut reader)?;
+
+        let mut ds64 = None;
+        let mut fmt_header = None;
+        let mut data = None;
+        let mut chunks = Vec::new();
+        let mut trailing_chunks = Vec::new();
+        let mut metadata = TrackMetadata::default();
+        let mut cues = Cues::default();
+        let mut iter = if lenient {
+            Chunks::lenient(reader)
+        } else {
+            Chunks::new(reader)
+        };
+        let mut last_id = String::new();
+        for chunk in &mut iter {
+            let (offset, chunk) = chunk?;
+            last_id = chunk.id.clone();
+            match chunk.id.as_str() {
+                "ds64" => ds64 = Some(DS64::new(&chunk, offset)?),
+                "fmt " => fmt_header = Some((offset, FMTHeader::new(&chunk, offset)?)),
+                "data" => data = Some((offset, chunk)),
+                _ => {
+                    let chunk = metadata
+                        .read_chunk(chunk)
+                        .and_then(|chunk| cues.read_chunk(chunk));
+                    if let Some(chunk) = chunk {
+                        match data {
+                            Some(_) => trailing_chunks.push(chunk),
+                            None => chunks.push(chunk),
+                        }
+                    }
+                }
+            }
+        }
+        let (fmt_offset, fmt_header) = fmt_header.ok_or(WavError::MissingChunk { id: "fmt " })?;
+        let (data_offset, data) = data.ok_or(WavError::MissingChunk { id: "data" })?;
+        let mut data_header = DataHeader::new(&data, data_offset)?;
+        if let (Some(size), "data") = (iter.truncated(), last_id.as_str()) {
+            data_header.size = u32::try_from(size).unwrap_or(u32::MAX);
+        }
+
+        let format = fmt_header.decodable(fmt_offset)?;
+        let signal = Samples::from_bytes::<LittleEndian>(format, &data.data);
+
+        let wav = WAV {
+            riff_header,
+            ds64,
+            fmt_header,
+            data_header,
+            signal,
+            chunks,
+            trailing_chunks,
+            metadata,
+            cues,
+            cursor: 0,
+        };
+
+        Ok(wav)
+    }
+
+    /// A WAV holding `signal`, interleaved over `nchannels` channels, or an
+    /// error if the samples do not make whole frames or a fmt chunk cannot
+    /// describe that layout.
+    pub fn new(sample_rate: u32, nchannels: u16, signal: Samples) -> Result<WAV, WavError> {
+        if nchannels == 0 || !signal.len().is_multiple_of(nchannels as usize) {
+            return Err(WavError::InvalidInput(format!(
+                "{} samples do not make whole frames of {} channels",
+                signal.len(),
+                nchannels
+            )));
+        }
+        let spec = WavSpec {
+            sample_rate,
+            nchannels,
+            format: signal.format(),
+        };
+        let mut wav = WAV {
+            riff_header: RIFFHeader {
+                riff: "RIFF".to_string(),
+                file_size: 0,
+                four_cc: "WAVE".to_string(),
+            },
+            ds64: None,
+            fmt_header: spec.fmt_header()?,
+            data_header: DataHeader {
+                data: "data".to_string(),
+                size: 0,
+            },
+            signal,
+            chunks: Vec::new(),
+            trailing_chunks: Vec::new(),
+            metadata: TrackMetadata::default(),
+            cues: Cues::default(),
+            cursor: 0,
+        };
+        wav.update_headers()?;
+        Ok(wav)
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        WavSpec {
+            sample_rate: self.fmt_header.sample_rate,
+            nchannels: self.fmt_header.nchannels,
+            format: self.signal.format(),
+        }
+    }
+
+    /// Recomputes the data size and the RIFF size from `signal` and the
+    /// chunks around it, so edits to the signal are always written out with
+    /// consistent headers. The fmt fields are only rewritten if the signal's
+    /// sample format no longer matches them; `repair` rewrites them anyway.
+    /// Files too large for 32 bit sizes, and files that were already RF64 or
+    /// BW64, get a `ds64` chunk.
+    pub fn update_headers(&mut self) -> Result<(), WavError> {
+        let spec = self.spec();
+        if self.fmt_header.sample_format() != Some(spec.format) {
+            self.fmt_header.update(&spec)?;
+        }
+        let (data_size, mut file_size) = self.sizes();
+
+        if self.riff_header.riff == "RIFF" && file_size <= u32::MAX as u64 {
+            self.ds64 = None;
+            self.riff_header.file_size = file_size as u32;
+            self.data_header.size = data_size as u32;
+            return Ok(());
+        }
+
+        let (before, after) = self.generated_chunks();
+        let table: Vec<(String, u64)> = self
+            .chunks
+            .iter()
+            .chain(self.trailing_chunks.iter())
+            .chain(before.iter().chain(after.iter()))
+            .filter(|c| c.data.len() as u64 >= u32::MAX as u64)
+            .map(|c| (c.id.clone(), c.data.len() as u64))
+            .collect();
+        let mut ds64 = DS64 {
+            riff_size: 0,
+            data_size,
+            sample_count: self.nframes() as u64,
+            table,
+        };
+        file_size += 8 + ds64.size() as u64;
+        ds64.riff_size = file_size;
+        self.ds64 = Some(ds64);
+        if self.riff_header.riff == "RIFF" {
+            self.riff_header.riff = "RF64".to_string();
+        }
+        self.riff_header.file_size = u32::MAX;
+        self.data_header.size = u32::MAX;
+        Ok(())
+    }
+
+    /// The chunks written from `metadata` and `cues`: those that go before
+    /// the data chunk and those that go after it.
+    fn generated_chunks(&self) -> (Vec<Chunk>, Vec<Chunk>) {
+        let (before, mut after) = self.metadata.to_chunks();
+        after.extend(self.cues.to_chunks(self.fmt_header.sample_rate));
+        (before, after)
+    }
+
+    /// The size of the data chunk payload holding `signal`, and the RIFF size
+    /// of the file without any `ds64` chunk.
+    fn sizes(&self) -> (u64, u64) {
+        let data_size = (self.signal.len() * self.signal.format().bytes_per_sample()) as u64;
+        let (before, after) = self.generated_chunks();
+        let chunk_size = |size: u64| 8 + size + size % 2;
+        let file_size = 4
+            + chunk_size(self.fmt_header.size() as u64)
+            + self
+                .chunks
+                .iter()
+                .chain(self.trailing_chunks.iter())
+                .chain(before.iter().chain(after.iter()))
+                .map(|c| chunk_size(c.data.len() as u64))
+                .sum::<u64>()
+            + chunk_size(data_size);
+        (data_size, file_size)
+    }
+
+    pub fn nchannels(&self) -> usize {
+        self.fmt_header.nchannels as usize
+    }
+
+    /// The number of whole frames, i.e. samples per channel.
+    pub fn nframes(&self) -> usize {
+        self.signal.len() / self.nchannels()
+    }
+
+    /// The normalized samples of channel `channel`.
+    pub fn channel(&self, channel: usize) -> impl Iterator<Item = f64> + '_ {
+        self.signal.channel(self.nchannels(), channel)
+    }
+
+    /// One normalized vector per channel.
+    pub fn channels(&self) -> Vec<Vec<f64>> {
+        self.signal.deinterleave(self.nchannels())
+    }
+
+    /// The normalized samples of each frame, one per channel.
+    pub fn frames(&self) -> impl Iterator<Item = Vec<f64>> + '_ {
+        self.signal.frames(self.nchannels())
+    }
+
+    /// The mean of every channel in each frame.
+    pub fn downmix(&self) -> Vec<f64> {
+        self.signal.downmix(self.nchannels())
+    }
+
+    pub fn select(&self, selection: ChannelSelection) -> Vec<f64> {
+        self.signal.select(self.nchannels(), selection)
+    }
+
+    pub fn write(self, filename: &str) -> Result<(), WavError> {
+        let f = File::create(filename)?;
+        let mut writer = BufWriter::new(f);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_to<W: Write>(mut self, writer: &mut W) -> Result<(), WavError> {
+        self.update_headers()?;
+        let data_size = self.signal.len() * self.signal.format().bytes_per_sample();
+        let (before, after) = self.generated_chunks();
+        self.riff_header.write(writer)?;
+        if let Some(ds64) = &self.ds64 {
+            ds64.write(writer)?;
+        }
+        self.fmt_header.write(writer)?;
+        for chunk in self.chunks.iter().chain(before.iter()) {
+            chunk.write(writer)?;
+        }
+        self.data_header.write(writer)?;
+        self.signal.write::<LittleEndian, _>(writer)?;
+        if data_size % 2 == 1 {
+            writer.write_u8(0)?;
+        }
+        for chunk in self.trailing_chunks.iter().chain(after.iter()) {
+            chunk.write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::{Extensible, Field, WavError, WAV, WAVE_FORMAT_PCM};
+    use crate::io::sample::Samples;
+    use std::fs::{remove_file, File};
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn lossless_read_write_1khz_file() {
+        let wav = WAV::from_file("data/1kHz_44100Hz_16bit_05sec.wav").unwrap();
+        wav.write("data/copy_1kHz.wav").unwrap();
+
+        let mut input_file = File::open("data/1kHz_44100Hz_16bit_05sec.wav").unwrap();
+        let mut input = Vec::new();
+        input_file.read_to_end(&mut input).unwrap();
+
+        let mut output_file = File::open("data/copy_1kHz.wav").unwrap();
+        let mut output = Vec::new();
+        output_file.read_to_end(&mut output).unwrap();
+        assert_eq!(input, output);
+
+        remove_file("data/copy_1kHz.wav").unwrap();
+    }
+
+    #[test]
+    fn unknown_chunks_survive_round_trip() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"RIFF");
+        input.extend_from_slice(&66u32.to_le_bytes());
+        input.extend_from_slice(b"WAVE");
+        input.extend_from_slice(b"fmt ");
+        input.extend_from_slice(&18u32.to_le_bytes());
+        input.extend_from_slice(&[1, 0, 1, 0, 0x44, 0xac, 0, 0, 0x88, 0x58, 1, 0, 2, 0, 16, 0]);
+        input.extend_from_slice(&[0, 0]);
+        input.extend_from_slice(b"JUNK");
+        input.extend_from_slice(&3u32.to_le_bytes());
+        input.extend_from_slice(&[1, 2, 3, 0]);
+        input.extend_from_slice(b"data");
+        input.extend_from_slice(&4u32.to_le_bytes());
+        input.extend_from_slice(&[1, 0, 2, 0]);
+        input.extend_from_slice(b"iXML");
+        input.extend_from_slice(&4u32.to_le_bytes());
+        input.extend_from_slice(b"<a/>");
+
+        let wav = WAV::from_reader(Cursor::new(&input)).unwrap();
+        assert_eq!(wav.fmt_header.sample_rate, 44100);
+        assert_eq!(wav.signal, Samples::I16(vec![1, 2]));
+        assert_eq!(wav.chunks[0].id, "JUNK");
+        assert_eq!(wav.trailing_chunks[0].id, "iXML");
+
+        let mut output = Vec::new();
+        wav.write_to(&mut output).unwrap();
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn headers_follow_edited_signal() {
+        let mut wav = WAV::from_file("data/1kHz_44100Hz_16bit_05sec.wav").unwrap();
+        if let Samples::I16(signal) = &mut wav.signal {
+            signal.drain(..4410);
+        }
+        let mut output = Vec::new();
+        wav.write_to(&mut output).unwrap();
+
+        let copy = WAV::from_reader(Cursor::new(&output)).unwrap();
+        assert_eq!(copy.data_header.size as usize, output.len() - 44);
+        assert_eq!(copy.riff_header.file_size as usize, output.len() - 8);
+        assert_eq!(copy.nframes(), 220500 - 4410);
+    }
+
+    /// 20 valid bits of stereo in 24 bit containers at 48 kHz, with a byte
+    /// rate that is off.
+    fn extensible_wav() -> Vec<u8> {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"RIFF");
+        input.extend_from_slice(&66u32.to_le_bytes());
+        input.extend_from_slice(b"WAVE");
+        input.extend_from_slice(b"fmt ");
+        input.extend_from_slice(&40u32.to_le_bytes());
+        input.extend_from_slice(&[
+            0xfe, 0xff, 2, 0, 0x80, 0xbb, 0, 0, 0x00, 0x19, 0x04, 0, 6, 0, 24, 0,
+        ]);
+        input.extend_from_slice(&[22, 0, 20, 0, 3, 0, 0, 0]);
+        input.extend_from_slice(&Extensible::from_format_tag(WAVE_FORMAT_PCM, 20, 3).sub_format);
+        input.extend_from_slice(b"data");
+        input.extend_from_slice(&6u32.to_le_bytes());
+        input.extend_from_slice(&[0x00, 0x00, 0x80, 0xf0, 0xff, 0x7f]);
+        input
+    }
+
+    #[test]
+    fn extensible_fmt_chunk_round_trip() {
+        let input = extensible_wav();
+        let wav = WAV::from_reader(Cursor::new(&input)).unwrap();
+        let extensible = wav.fmt_header.extensible.as_ref().unwrap();
+        assert_eq!(extensible.valid_bits_per_sample, 20);
+        assert_eq!(extensible.channel_mask, 3);
+        assert_eq!(wav.fmt_header.format_tag(), WAVE_FORMAT_PCM);
+        assert_eq!(wav.signal, Samples::I24(vec![-8_388_608, 8_388_592]));
+
+        let mut output = Vec::new();
+        wav.write_to(&mut output).unwrap();
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn only_repair_rewrites_fmt_fields() {
+        let input = extensible_wav();
+        let mut wav = WAV::from_reader(Cursor::new(&input)).unwrap();
+        let issues = wav.repair().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, Field::ByteRate);
+        assert_eq!(wav.fmt_header.byte_rate, 288000);
+
+        let mut output = Vec::new();
+        wav.write_to(&mut output).unwrap();
+        let mut expected = input;
+        expected[29] = 0x65;
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn oversized_layout_is_an_error() {
+        let signal = Samples::F64(vec![0.0; 65535]);
+        match WAV::new(44100, 65535, signal) {
+            Err(WavError::InvalidInput(_)) => {}
+            other => panic!("{:?}", other.map(|wav| wav.nframes())),
+        }
+        let signal = Samples::I16(vec![0; 2]);
+        assert!(WAV::new(u32::MAX, 2, signal).is_err());
+        for &(nchannels, len) in &[(0, 2), (2, 3)] {
+            let signal = Samples::I16(vec![1; len]);
+            match WAV::new(44100, nchannels, signal) {
+                Err(WavError::InvalidInput(_)) => {}
+                other => panic!("{:?}", other.map(|wav| wav.nframes())),
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_past_cb_size_are_dropped() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"RIFF");
+        input.extend_from_slice(&62u32.to_le_bytes());
+        input.extend_from_slice(b"WAVE");
+        input.extend_from_slice(b"fmt ");
+        input.extend_from_slice(&42u32.to_le_bytes());
+        input.extend_from_slice(&[
+            0xfe, 0xff, 1, 0, 0x44, 0xac, 0, 0, 0x88, 0x58, 0x01, 0, 2, 0, 16, 0,
+        ]);
+        input.extend_from_slice(&[22, 0, 16, 0, 4, 0, 0, 0]);
+        input.extend_from_slice(&Extensible::from_format_tag(WAVE_FORMAT_PCM, 16, 4).sub_format);
+        input.extend_from_slice(&[0xaa, 0xbb]);
+        input.extend_from_slice(b"data");
+        input.extend_from_slice(&2u32.to_le_bytes());
+        input.extend_from_slice(&[0x01, 0x00]);
+
+        let wav = WAV::from_reader(Cursor::new(&input)).unwrap();
+        assert!(wav.fmt_header.extension.is_empty());
+        assert_eq!(wav.fmt_header.size(), 40);
+        assert_eq!(wav.signal, Samples::I16(vec![1]));
+    }
+}
+
+#[cfg(test)]
+mod errors {
+    use super::{WavError, WAV};
+    use std::io::Cursor;
+
+    /// A 16 bit mono file holding `data`.
+    fn wav_bytes(format: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&format.to_le_bytes());
+        bytes.extend_from_slice(&[1, 0, 0x44, 0xac, 0, 0, 0x88, 0x58, 1, 0, 2, 0, 16, 0]);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn truncated_data_chunk() {
+        let mut bytes = wav_bytes(1, &[0; 10]);
+        bytes.truncate(bytes.len() - 4);
+        match WAV::from_reader(Cursor::new(bytes)) {
+            Err(WavError::Truncated {
+                offset: 36,
+                expected: 18,
+                found: 14,
+            }) => (),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = wav_bytes(1, &[0; 2]);
+        bytes[8..12].copy_from_slice(b"AVI ");
+        match WAV::from_reader(Cursor::new(bytes)) {
+            Err(WavError::BadMagic {
+                offset: 8, found, ..
+            }) => assert_eq!(found, "AVI "),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let bytes = wav_bytes(2, &[0; 2]);
+        match WAV::from_reader(Cursor::new(bytes)) {
+            Err(WavError::Unsupported { offset: 12, reason }) => {
+                assert_eq!(reason, "format tag 0x0002 with 16 bits per sample")
+            }
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn odd_length_data_chunk() {
+        let wav = WAV::from_reader(Cursor::new(wav_bytes(1, &[1, 0, 2]))).unwrap();
+        assert_eq!(wav.nframes(), 1);
+    }
+
+    #[test]
+    fn missing_data_chunk() {
+        let mut bytes = wav_bytes(1, &[]);
+        bytes.truncate(36);
+        match WAV::from_reader(Cursor::new(bytes)) {
+            Err(WavError::MissingChunk { id: "data" }) => (),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use byteorder::LittleEndian;
+
+use super::{
+    read_chunk_body, read_chunk_header, read_full, read_riff_header, Chunk, Chunks, Cues,
+    FMTHeader, RIFFHeader, TrackMetadata, WavError, DS64,
+};
+use crate::io::sample::{SampleFormat, Samples};
+
+/// Reads the frames of a WAV file on demand instead of loading the whole data
+/// chunk into memory. Only the headers and the chunks before `data` are read
+/// up front.
+pub struct WavReader<R> {
+    reader: R,
+    pub riff_header: RIFFHeader,
+    pub ds64: Option<DS64>,
+    pub fmt_header: FMTHeader,
+    /// chunks cldj does not interpret which appeared before the data chunk
+    pub chunks: Vec<Chunk>,
+    /// tags from anywhere in the file
+    pub metadata: TrackMetadata,
+    /// cue points and loops from anywhere in the file
+    pub cues: Cues,
+    format: SampleFormat,
+    data_start: u64,
+    nframes: u64,
+    position: u64,
+    buf: Vec<u8>,
+}
+
+impl WavReader<BufReader<File>> {
+    pub fn open(filename: &str) -> Result<WavReader<BufReader<File>>, WavError> {
+        let f = File::open(filename)?;
+        WavReader::new(BufReader::new(f))
+    }
+}
+
+impl<R: Read + Seek> WavReader<R> {
+    pub fn new(mut reader: R) -> Result<WavReader<R>, WavError> {
+        let riff_header = read_riff_header(&mut reader)?;
+
+        let mut ds64: Option<DS64> = None;
+        let mut fmt_header = None;
+        let mut chunks = Vec::new();
+        let mut metadata = TrackMetadata::default();
+        let mut cues = Cues::default();
+        let (data_start, data_size) = loop {
+            let offset = reader.stream_position()?;
+            let (id, size) = read_chunk_header(&mut reader, offset)?
+                .ok_or(WavError::MissingChunk { id: "data" })?;
+            let size = match &ds64 {
+                Some(ds64) => ds64.chunk_size(&id, size),
+                None => size as u64,
+            };
+            if id == "data" {
+                break (offset + 8, size);
+            }
+            let chunk = read_chunk_body(&mut reader, offset, id, size)?;
+            match chunk.id.as_str() {
+                "ds64" => ds64 = Some(DS64::new(&chunk, offset)?),
+                "fmt " => fmt_header = Some((offset, FMTHeader::new(&chunk, offset)?)),
+                _ => chunks.extend(
+                    metadata
+                        .read_chunk(chunk)
+                        .and_then(|chunk| cues.read_chunk(chunk)),
+                ),
+            }
+        };
+        let (fmt_offset, fmt_header) = fmt_header.ok_or(WavError::MissingChunk { id: "fmt " })?;
+        let format = fmt_header.decodable(fmt_offset)?;
+
+        // files written by an interrupted recorder often claim more data than
+        // they hold, so trust the file length over the header
+        let available = reader.seek(SeekFrom::End(0))? - data_start;
+        let frame_size = (format.bytes_per_sample() * fmt_header.nchannels as usize) as u64;
+        let nframes = data_size.min(available) / frame_size;
+
+        // cue and id3 chunks are usually written after the data; a broken
+        // chunk there only costs those, so stop at the first error
+        if data_size < available {
+            let end = data_start + data_size + data_size % 2;
+            reader.seek(SeekFrom::Start(end))?;
+            let mut trailing = Chunks::lenient(&mut reader);
+            trailing.offset = end;
+            trailing.ds64 = ds64.clone();
+            for (_, chunk) in trailing.map_while(Result::ok) {
+                if let Some(chunk) = metadata.read_chunk(chunk) {
+                    cues.read_chunk(chunk);
+                }
+            }
+        }
+        reader.seek(SeekFrom::Start(data_start))?;
+
+        Ok(WavReader {
+            reader,
+            riff_header,
+            ds64,
+            fmt_header,
+            chunks,
+            metadata,
+            cues,
+            format,
+            data_start,
+            nframes,
+            position: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.fmt_header.sample_rate
+    }
+
+    pub fn nchannels(&self) -> usize {
+        self.fmt_header.nchannels as usize
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.format
+    }
+
+    /// The number of whole frames in the data chunk.
+    pub fn nframes(&self) -> u64 {
+        self.nframes
+    }
+
+    /// The index of the next frame to be read.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn frame_size(&self) -> usize {
+        self.format.bytes_per_sample() * self.nchannels()
+    }
+
+    /// Moves to frame `frame`; seeking past the end leaves the reader at the end.
+    pub fn seek(&mut self, frame: u64) -> Result<(), WavError> {
+        let frame = frame.min(self.nframes);
+        let offset = self.data_start + frame * self.frame_size() as u64;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.position = frame;
+        Ok(())
+    }
+
+    /// Reads up to `nframes` frames of interleaved samples in their stored
+    /// format. Fewer frames are returned near the end of the data chunk.
+    pub fn read_samples(&mut self, nframes: usize) -> Result<Samples, WavError> {
+        let nframes = nframes.min((self.nframes - self.position) as usize);
+        let size = nframes * self.frame_size();
+        self.buf.resize(size, 0);
+        let read = read_full(&mut self.reader, &mut self.buf)?;
+        let nframes = read / self.frame_size();
+        self.position += nframes as u64;
+        let samples = &self.buf[..nframes * self.frame_size()];
+        Ok(Samples::from_bytes::<LittleEndian>(self.format, samples))
+    }
+
+    /// Fills `buf` with normalized interleaved samples, returning the number of
+    /// whole frames read. Zero means the end of the data chunk was reached.
+    pub fn read_frames(&mut self, buf: &mut [f64]) -> Result<usize, WavError> {
+        let samples = self.read_samples(buf.len() / self.nchannels())?;
+        for (n, x) in buf.iter_mut().take(samples.len()).enumerate() {
+            *x = samples.get_f64(n);
+        }
+        Ok(samples.len() / self.nchannels())
+    }
+}
+
+impl<R: Read + Seek> Iterator for WavReader<R> {
+    type Item = Result<Vec<f64>, WavError>;
+
+    /// The next frame as one normalized sample per channel.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = vec![0.0; self.nchannels()];
+        match self.read_frames(&mut frame) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(frame)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod streaming {
+    use super::WavReader;
+    use crate::io::sample::ChannelSelection;
+    use crate::io::wav::WAV;
+
+    const FILE: &str = "data/1kHz_44100Hz_16bit_05sec.wav";
+
+    #[test]
+    fn matches_in_memory_read() {
+        let wav = WAV::from_file(FILE).unwrap();
+        let mut reader = WavReader::open(FILE).unwrap();
+        assert_eq!(reader.nframes(), wav.nframes() as u64);
+        assert_eq!(reader.sample_rate(), wav.fmt_header.sample_rate);
+
+        let mut buf = vec![0.0; 1000];
+        let mut streamed = Vec::new();
+        loop {
+            let n = reader.read_frames(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(streamed, wav.select(ChannelSelection::Channel(0)));
+    }
+
+    #[test]
+    fn seek_by_frame() {
+        let wav = WAV::from_file(FILE).unwrap();
+        let mut reader = WavReader::open(FILE).unwrap();
+        reader.seek(44100).unwrap();
+        let frame = reader.next().unwrap().unwrap();
+        assert_eq!(frame[0], wav.signal.get_f64(44100));
+        assert_eq!(reader.position(), 44101);
+
+        reader.seek(u64::MAX).unwrap();
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt;
+
+use super::{FMTHeader, WavError, WAV};
+
+/// A header field `validate` checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    ByteRate,
+    BlockAlign,
+    ValidBitsPerSample,
+    RiffSize,
+    DataSize,
+    SampleCount,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Field::ByteRate => "byte rate",
+            Field::BlockAlign => "block align",
+            Field::ValidBitsPerSample => "valid bits per sample",
+            Field::RiffSize => "RIFF size",
+            Field::DataSize => "data size",
+            Field::SampleCount => "ds64 sample count",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A header field whose value disagrees with the rest of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub field: Field,
+    pub found: u64,
+    pub expected: u64,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is {}, expected {}",
+            self.field, self.found, self.expected
+        )
+    }
+}
+
+/// Records an `Issue` in `issues` if `found` is not `expected`.
+fn check(issues: &mut Vec<Issue>, field: Field, found: u64, expected: u64) {
+    if found != expected {
+        issues.push(Issue {
+            field,
+            found,
+            expected,
+        });
+    }
+}
+
+impl FMTHeader {
+    /// Checks the fields derived from the channel count, sample rate and
+    /// sample size against those three. Decoding never reads the derived
+    /// fields, so a file can play fine and still fail here.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let bits = self.bits_per_sample as u64;
+        let block_align = self.nchannels as u64 * bits.div_ceil(8);
+        check(
+            &mut issues,
+            Field::BlockAlign,
+            self.block_align as u64,
+            block_align,
+        );
+        check(
+            &mut issues,
+            Field::ByteRate,
+            self.byte_rate as u64,
+            self.sample_rate as u64 * block_align,
+        );
+        if let Some(extensible) = &self.extensible {
+            let valid_bits = extensible.valid_bits_per_sample as u64;
+            if valid_bits == 0 || valid_bits > bits {
+                check(&mut issues, Field::ValidBitsPerSample, valid_bits, bits);
+            }
+        }
+        issues
+    }
+}
+
+impl WAV {
+    /// Checks every header field against the signal and chunks actually
+    /// present, returning the fields that are wrong. An empty list means
+    /// `write` would reproduce the headers unchanged.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = self.fmt_header.validate();
+        let (data_size, riff_size) = self.sizes();
+        match &self.ds64 {
+            Some(ds64) => {
+                let riff_size = riff_size + 8 + ds64.size() as u64;
+                check(&mut issues, Field::RiffSize, ds64.riff_size, riff_size);
+                check(&mut issues, Field::DataSize, ds64.data_size, data_size);
+                check(
+                    &mut issues,
+                    Field::SampleCount,
+                    ds64.sample_count,
+                    self.nframes() as u64,
+                );
+            }
+            None => {
+                let found = self.riff_header.file_size as u64;
+                check(&mut issues, Field::RiffSize, found, riff_size);
+                let found = self.data_header.size as u64;
+                check(&mut issues, Field::DataSize, found, data_size);
+            }
+        }
+        issues
+    }
+
+    /// Rewrites every header field `validate` checks to match the signal,
+    /// returning the issues that were fixed. Fails, changing nothing, if the
+    /// fmt chunk's layout is too large for its own derived fields.
+    pub fn repair(&mut self) -> Result<Vec<Issue>, WavError> {
+        let issues = self.validate();
+        let spec = self.spec();
+        self.fmt_header.update(&spec)?;
+        self.update_headers()?;
+        Ok(issues)
+    }
+}
+
+#[cfg(test)]
+mod repair {
+    use super::{Field, Issue};
+    use crate::io::wav::{WavError, WAV};
+    use std::io::Cursor;
+
+    /// A 16 bit stereo file at 44.1 kHz with the given header fields.
+    fn wav_bytes(riff_size: u32, byte_rate: u32, block_align: u16, data_size: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&riff_size.to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&[1, 0, 2, 0, 0x44, 0xac, 0, 0]);
+        bytes.extend_from_slice(&byte_rate.to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        bytes.extend_from_slice(&[1, 0, 2, 0, 3, 0, 4, 0]);
+        bytes
+    }
+
+    #[test]
+    fn consistent_file_has_no_issues() {
+        let bytes = wav_bytes(44, 176400, 4, 8);
+        let wav = WAV::from_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(wav.validate(), vec![]);
+
+        let wav = WAV::from_file("data/1kHz_44100Hz_16bit_05sec.wav").unwrap();
+        assert_eq!(wav.validate(), vec![]);
+    }
+
+    #[test]
+    fn wrong_fmt_and_riff_fields_are_repaired() {
+        let bytes = wav_bytes(0, 88200, 2, 8);
+        let mut wav = WAV::from_reader(Cursor::new(bytes)).unwrap();
+        let issue = |field, found, expected| Issue {
+            field,
+            found,
+            expected,
+        };
+        assert_eq!(
+            wav.repair().unwrap(),
+            vec![
+                issue(Field::BlockAlign, 2, 4),
+                issue(Field::ByteRate, 88200, 176400),
+                issue(Field::RiffSize, 0, 44),
+            ]
+        );
+        assert_eq!(wav.validate(), vec![]);
+
+        let mut repaired = Vec::new();
+        wav.write_to(&mut repaired).unwrap();
+        assert_eq!(repaired, wav_bytes(44, 176400, 4, 8));
+    }
+
+    #[test]
+    fn truncated_data_is_kept() {
+        // an interrupted recording: the data size claims 100 bytes but only
+        // 8 were written
+        let bytes = wav_bytes(136, 176400, 4, 100);
+        assert!(WAV::from_reader(Cursor::new(&bytes)).is_err());
+
+        let mut wav = WAV::from_reader_lenient(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.nframes(), 2);
+        assert_eq!(
+            wav.repair().unwrap(),
+            vec![
+                Issue {
+                    field: Field::RiffSize,
+                    found: 136,
+                    expected: 44,
+                },
+                Issue {
+                    field: Field::DataSize,
+                    found: 100,
+                    expected: 8,
+                },
+            ]
+        );
+        let mut repaired = Vec::new();
+        wav.write_to(&mut repaired).unwrap();
+        assert_eq!(repaired, wav_bytes(44, 176400, 4, 8));
+    }
+
+    #[test]
+    fn oversized_layout_is_an_error() {
+        // 65535 channels of f64 overflow the block align field
+        let mut bytes = wav_bytes(36, 0, 0, 0);
+        bytes.truncate(44);
+        bytes[20..24].copy_from_slice(&[3, 0, 0xff, 0xff]);
+        bytes[34..36].copy_from_slice(&64u16.to_le_bytes());
+        let mut wav = WAV::from_reader_lenient(Cursor::new(&bytes)).unwrap();
+        match wav.repair() {
+            Err(WavError::InvalidInput(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(wav.fmt_header.block_align, 0);
+    }
+}
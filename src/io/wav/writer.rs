@@ -0,0 +1,296 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::{Chunk, DataHeader, RIFFHeader, WavError, WavSpec, DS64};
+use crate::io::sample::Samples;
+
+/// Writes a WAV file a few frames at a time. The RIFF and data sizes are
+/// unknown until the last frame, so placeholders are written up front and
+/// patched by `finalize`, which also runs when the writer is dropped.
+///
+/// A `JUNK` chunk the size of a `ds64` chunk is reserved after the RIFF
+/// header; if the file outgrows 32 bit sizes it is turned into an RF64 file
+/// by overwriting that chunk in place.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    block_align: u16,
+    data_start: u64,
+    data_size: u64,
+    rf64_threshold: u64,
+    finalized: bool,
+}
+
+/// Where the reserved `JUNK`/`ds64` chunk starts.
+const DS64_OFFSET: u64 = 12;
+/// The payload size of a `ds64` chunk with an empty table.
+const DS64_SIZE: usize = 28;
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(filename: &str, spec: WavSpec) -> Result<WavWriter<BufWriter<File>>, WavError> {
+        let f = File::create(filename)?;
+        WavWriter::new(BufWriter::new(f), spec)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, spec: WavSpec) -> Result<WavWriter<W>, WavError> {
+        if spec.nchannels == 0 {
+            return Err(WavError::InvalidInput(
+                "cannot write a WAV file with zero channels".to_string(),
+            ));
+        }
+        let fmt_header = spec.fmt_header()?;
+        let riff_header = RIFFHeader {
+            riff: "RIFF".to_string(),
+            file_size: 0,
+            four_cc: "WAVE".to_string(),
+        };
+        riff_header.write(&mut writer)?;
+        let junk = Chunk {
+            id: "JUNK".to_string(),
+            data: vec![0; DS64_SIZE],
+        };
+        junk.write(&mut writer)?;
+        let block_align = fmt_header.block_align;
+        fmt_header.write(&mut writer)?;
+        let data_header = DataHeader {
+            data: "data".to_string(),
+            size: 0,
+        };
+        data_header.write(&mut writer)?;
+        let data_start = writer.stream_position()?;
+        Ok(WavWriter {
+            writer,
+            spec,
+            block_align,
+            data_start,
+            data_size: 0,
+            rf64_threshold: u32::MAX as u64,
+            finalized: false,
+        })
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// The number of whole frames written so far.
+    pub fn nframes(&self) -> u64 {
+        self.data_size / self.block_align as u64
+    }
+
+    /// Writes one frame of normalized samples, one per channel.
+    pub fn write_frame(&mut self, frame: &[f64]) -> Result<(), WavError> {
+        if frame.len() != self.spec.nchannels as usize {
+            return Err(WavError::InvalidInput(format!(
+                "frame has {} samples but the file has {} channels",
+                frame.len(),
+                self.spec.nchannels
+            )));
+        }
+        self.write_frames(frame)
+    }
+
+    /// Writes whole frames of interleaved normalized samples.
+    pub fn write_frames(&mut self, interleaved: &[f64]) -> Result<(), WavError> {
+        if !interleaved
+            .len()
+            .is_multiple_of(self.spec.nchannels as usize)
+        {
+            return Err(WavError::InvalidInput(
+                "interleaved samples do not divide into whole frames".to_string(),
+            ));
+        }
+        let samples = Samples::from_f64(self.spec.format, interleaved);
+        self.write_samples(&samples)
+    }
+
+    /// Writes interleaved samples already in the file's sample format.
+    pub fn write_samples(&mut self, samples: &Samples) -> Result<(), WavError> {
+        if self.finalized {
+            return Err(WavError::InvalidInput(
+                "cannot write to a finalized WavWriter".to_string(),
+            ));
+        }
+        if samples.format() != self.spec.format {
+            return Err(WavError::InvalidInput(format!(
+                "samples are {:?} but the file is {:?}",
+                samples.format(),
+                self.spec.format
+            )));
+        }
+        samples.write::<LittleEndian, _>(&mut self.writer)?;
+        self.data_size += (samples.len() * self.spec.format.bytes_per_sample()) as u64;
+        Ok(())
+    }
+
+    /// Pads the data chunk and patches the RIFF and data sizes, promoting the
+    /// file to RF64 if they do not fit in 32 bits. Calling this more than once
+    /// has no further effect.
+    pub fn finalize(&mut self) -> Result<(), WavError> {
+        if self.finalized {
+            return Ok(());
+        }
+        self.finalized = true;
+        if self.data_size % 2 == 1 {
+            self.writer.write_u8(0)?;
+        }
+        let end = self.writer.stream_position()?;
+        let riff_size = end - 8;
+        if riff_size > self.rf64_threshold {
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.writer.write_all(b"RF64")?;
+            self.writer.write_u32::<LittleEndian>(u32::MAX)?;
+            let ds64 = DS64 {
+                riff_size,
+                data_size: self.data_size,
+                sample_count: self.nframes(),
+                table: Vec::new(),
+            };
+            self.writer.seek(SeekFrom::Start(DS64_OFFSET))?;
+            ds64.write(&mut self.writer)?;
+            self.writer.seek(SeekFrom::Start(self.data_start - 4))?;
+            self.writer.write_u32::<LittleEndian>(u32::MAX)?;
+        } else {
+            self.writer.seek(SeekFrom::Start(4))?;
+            self.writer.write_u32::<LittleEndian>(riff_size as u32)?;
+            self.writer.seek(SeekFrom::Start(self.data_start - 4))?;
+            self.writer
+                .write_u32::<LittleEndian>(self.data_size as u32)?;
+        }
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        // errors can't be reported from drop; call finalize to see them
+        let _ = self.finalize();
+    }
+}
+
+#[cfg(test)]
+mod there_and_back_again {
+    use super::WavWriter;
+    use crate::io::sample::{ChannelSelection, SampleFormat, Samples};
+    use crate::io::wav::{WavReader, WavSpec, WAV};
+    use std::io::Cursor;
+
+    fn spec(format: SampleFormat) -> WavSpec {
+        WavSpec {
+            sample_rate: 48000,
+            nchannels: 2,
+            format,
+        }
+    }
+
+    #[test]
+    fn sizes_are_patched_on_finalize() {
+        let mut output = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut output, spec(SampleFormat::I16)).unwrap();
+        writer.write_frame(&[0.5, -0.5]).unwrap();
+        writer.write_frames(&[0.25, -0.25, 0.0, 0.0]).unwrap();
+        assert_eq!(writer.nframes(), 3);
+        writer.finalize().unwrap();
+        drop(writer);
+
+        let bytes = output.into_inner();
+        assert_eq!(bytes.len(), 80 + 12);
+        let wav = WAV::from_reader(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.riff_header.riff, "RIFF");
+        assert_eq!(wav.riff_header.file_size, 72 + 12);
+        assert_eq!(wav.data_header.size, 12);
+        assert_eq!(wav.fmt_header.byte_rate, 48000 * 4);
+        assert_eq!(wav.fmt_header.block_align, 4);
+        assert_eq!(
+            wav.select(ChannelSelection::Channel(0)),
+            vec![0.5, 0.25, 0.0]
+        );
+    }
+
+    #[test]
+    fn drop_finalizes_and_pads() {
+        let mut output = Cursor::new(Vec::new());
+        {
+            let spec = WavSpec {
+                nchannels: 1,
+                ..spec(SampleFormat::U8)
+            };
+            let mut writer = WavWriter::new(&mut output, spec).unwrap();
+            writer
+                .write_samples(&Samples::U8(vec![1, 2, 3, 4, 5, 6]))
+                .unwrap();
+            writer.write_samples(&Samples::U8(vec![7])).unwrap();
+        }
+        let bytes = output.into_inner();
+        assert_eq!(bytes.len(), 80 + 8);
+        let wav = WAV::from_reader(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.data_header.size, 7);
+        assert_eq!(wav.signal, Samples::U8(vec![1, 2, 3, 4, 5, 6, 7]));
+    }
+
+    #[test]
+    fn matches_in_memory_write() {
+        let signal = Samples::from_f64(SampleFormat::F32, &[0.5, -0.5, 0.25, -0.25]);
+        let mut output = Cursor::new(Vec::new());
+        WavWriter::new(&mut output, spec(SampleFormat::F32))
+            .unwrap()
+            .write_samples(&signal)
+            .unwrap();
+
+        // the same file with the writer's reserved JUNK chunk after the RIFF
+        // header
+        let mut expected = Vec::new();
+        WAV::new(48000, 2, signal)
+            .unwrap()
+            .write_to(&mut expected)
+            .unwrap();
+        let mut junk = b"JUNK".to_vec();
+        junk.extend_from_slice(&28u32.to_le_bytes());
+        junk.extend_from_slice(&[0; 28]);
+        expected.splice(12..12, junk);
+        let riff_size = expected.len() as u32 - 8;
+        expected[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        assert_eq!(output.into_inner(), expected);
+    }
+
+    #[test]
+    fn promotes_to_rf64() {
+        let mut output = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut output, spec(SampleFormat::I16)).unwrap();
+        writer.rf64_threshold = 64;
+        writer.write_frames(&[0.5, -0.5, 0.25, -0.25]).unwrap();
+        drop(writer);
+
+        let bytes = output.into_inner();
+        let wav = WAV::from_reader(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.riff_header.riff, "RF64");
+        assert_eq!(wav.riff_header.file_size, u32::MAX);
+        let ds64 = wav.ds64.as_ref().unwrap();
+        assert_eq!(ds64.riff_size, bytes.len() as u64 - 8);
+        assert_eq!(ds64.data_size, 8);
+        assert_eq!(ds64.sample_count, 2);
+        assert_eq!(wav.select(ChannelSelection::Channel(1)), vec![-0.5, -0.25]);
+
+        let reader = WavReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.nframes(), 2);
+
+        // an RF64 file stays RF64 when written back
+        let mut copy = Vec::new();
+        wav.write_to(&mut copy).unwrap();
+        assert_eq!(bytes, copy);
+    }
+
+    #[test]
+    fn rejects_mismatched_format() {
+        let mut output = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut output, spec(SampleFormat::I24)).unwrap();
+        assert!(writer.write_samples(&Samples::I16(vec![0, 0])).is_err());
+        assert!(writer.write_frame(&[0.0]).is_err());
+    }
+}
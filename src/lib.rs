@@ -1,3 +1,5 @@
 pub mod io;
 pub mod display;
+pub mod generator;
+pub mod synth;
 pub mod transform;
@@ -1,53 +1,166 @@
-use std::{error::Error};
+use std::{env, error::Error, fs, process};
 
-use cldj::io::wav::WAV;
 use cldj::display;
+use cldj::io::midi::Smf;
+use cldj::io::raw::{self, Endianness, Layout};
+use cldj::io::sample::ChannelSelection;
+use cldj::io::wav::WAV;
+use cldj::synth::Synth;
+use cldj::transform::{Spectrum, Window};
 
+const USAGE: &str = "usage:
+    cldj display <file> [channel]
+    cldj peak <file> [channel]
+    cldj repair <file> [output]
+    cldj render <midi file> <output> [sample rate]
+    cldj raw <input> <output> <u8|i16|i24|i32|f32|f64> <channels> <sample rate> [le|be]";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2)
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut wav = WAV::from_file("data/1kHz_44100Hz_16bit_05sec.wav")?;
-    wav.write("data/copy_1kHz.wav")?;
-
-
-    let mut wav = WAV::from_file("data/100Hz_44100Hz_16bit_05sec.wav")?;
-
-    //let (_, format_header, _, mut signal) = WAV::from_file("data/100Hz_44100Hz_16bit_05sec.wav")?;
-    //let (_, format_header, _, mut signal) = wav::read_file("data/1kHz_44100Hz_16bit_05sec.wav");
-
-    let sample_rate = wav.fmt_header.sample_rate as usize;
-    let fourier_output_length = sample_rate / 10;
-//    println!("sample rate: {}", sample_rate);
-
-    //100Hz frequency
-    //44100Hz sampling frequency
-    //44100Hz / 100Hz = 441 oversampling factor
-    //5 seconds
-    //100 * 441 * 5 = 220500 samples
-//    println!("n samples: {}", signal.len());
-
-    let head = wav.signal.drain(..fourier_output_length).collect::<Vec<i16>>();
-//    let result = fourier_transform(head);
-//
-//    //2 ** 12 = 4096
-//    //44100Hz / 4096 = 10.7666 
-//    //100 / 10.7666 = 9.28798
-//    // => spike at 9.28798
-//    //
-//    // 44100Hz sampling rate / 4410 vector length => ith position = i * 10Hz frequency
-//    // therefore we expect a spike at the 10th index
-//
-//
-//    let mut foo: Vec<f64> = Vec::new();
-//    //println!("{:#?}", result);
-//    for x in result {
-//        foo.push(x.norm());
-//        //println!("{}", x.norm())
-//    }
-//    let half = foo.drain(..150).collect::<Vec<f64>>();
-//    for (i, x) in half.iter().enumerate() {
-//        println!("{}: {}", i, x);
-//    }
-    //display::run(head)?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("display") => display_command(&args[1..]),
+        Some("peak") => peak_command(&args[1..]),
+        Some("repair") => repair_command(&args[1..]),
+        Some("render") => render_command(&args[1..]),
+        Some("raw") => raw_command(&args[1..]),
+        _ => usage(),
+    }
+}
+
+/// Shows the waveform and spectrum of one channel, or of all channels mixed
+/// down when none is given.
+fn display_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (filename, selection) = match args {
+        [filename] => (filename, ChannelSelection::Downmix),
+        [filename, channel] => (filename, ChannelSelection::Channel(channel.parse()?)),
+        _ => usage(),
+    };
+    let source = cldj::io::open(filename)?;
+    check_channel(selection, source.nchannels())?;
+    display::run(source, selection)
+}
+
+/// Prints the dominant frequency of one channel, or of all channels mixed
+/// down when none is given, over the whole file.
+fn peak_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (filename, selection) = match args {
+        [filename] => (filename, ChannelSelection::Downmix),
+        [filename, channel] => (filename, ChannelSelection::Channel(channel.parse()?)),
+        _ => usage(),
+    };
+    let mut source = cldj::io::open(filename)?;
+    let nchannels = source.nchannels();
+    check_channel(selection, nchannels)?;
+    let mut signal = Vec::new();
+    let mut buf = vec![0.0; 4096 * nchannels];
+    loop {
+        let read = source.read_frames(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        signal.extend(
+            buf.chunks_exact(nchannels)
+                .take(read)
+                .map(|frame| selection.pick(frame)),
+        );
+    }
+    let spectrum: Spectrum = Spectrum::new(signal, source.sample_rate(), Window::Hann);
+    match spectrum.peak() {
+        Some(peak) => println!("dominant frequency: {:.1} Hz", peak.frequency),
+        None => println!("{}: no samples", filename),
+    }
+    Ok(())
+}
+
+/// Refuses a channel the file does not have. Channels count from 0.
+fn check_channel(selection: ChannelSelection, nchannels: usize) -> Result<(), Box<dyn Error>> {
+    match selection {
+        ChannelSelection::Channel(c) if c >= nchannels => Err(format!(
+            "there is no channel {}: the file has {} channel{}",
+            c,
+            nchannels,
+            if nchannels == 1 { "" } else { "s" }
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Reports inconsistent header fields and rewrites the file, or a copy of it
+/// at `output`, with corrected headers. The file is replaced by renaming a
+/// repaired copy over it, so a failed write leaves the original intact.
+fn repair_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (filename, output) = match args {
+        [filename] => (filename, filename),
+        [filename, output] => (filename, output),
+        _ => usage(),
+    };
+    let mut wav = WAV::from_file_lenient(filename)?;
+    let issues = wav.repair()?;
+    if issues.is_empty() && output == filename {
+        println!("{}: no problems found", filename);
+        return Ok(());
+    }
+    for issue in &issues {
+        println!("{}: {}", filename, issue);
+    }
+    if output == filename {
+        let temp = format!("{}.repair", filename);
+        if let Err(e) = wav.write(&temp) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        fs::rename(&temp, filename)?;
+    } else {
+        wav.write(output)?;
+    }
+    println!("wrote {}", output);
     Ok(())
 }
 
+/// Plays a MIDI file through the built-in synth into a WAV file, at 44.1 kHz
+/// unless another sample rate is given.
+fn render_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (filename, output, sample_rate) = match args {
+        [filename, output] => (filename, output, 44100),
+        [filename, output, sample_rate] => (filename, output, sample_rate.parse()?),
+        _ => usage(),
+    };
+    let smf = Smf::from_file(filename)?;
+    Synth::new(sample_rate).render_wav(&smf)?.write(output)?;
+    println!("wrote {}", output);
+    Ok(())
+}
+
+/// Wraps a headerless PCM dump in a WAV file, reading it as little endian
+/// unless `be` is given.
+fn raw_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (filename, output, format, nchannels, sample_rate, endianness) = match args {
+        [filename, output, format, nchannels, sample_rate] => {
+            (filename, output, format, nchannels, sample_rate, "le")
+        }
+        [filename, output, format, nchannels, sample_rate, endianness] => (
+            filename,
+            output,
+            format,
+            nchannels,
+            sample_rate,
+            endianness.as_str(),
+        ),
+        _ => usage(),
+    };
+    let layout = Layout {
+        format: raw::parse_format(format).unwrap_or_else(|| usage()),
+        endianness: Endianness::parse(endianness).unwrap_or_else(|| usage()),
+        nchannels: nchannels.parse()?,
+        sample_rate: sample_rate.parse()?,
+    };
+    raw::from_file(filename, layout)?.write(output)?;
+    println!("wrote {}", output);
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use crate::generator::{Oscillator, Waveform};
+use crate::io::midi::{EventKind, Smf};
+use crate::io::sample::{SampleFormat, Samples};
+use crate::io::wav::{WavError, WAV};
+
+/// The MIDI channel General MIDI reserves for drums, zero indexed.
+const DRUM_CHANNEL: u8 = 9;
+
+/// An attack, decay, sustain, release envelope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    /// seconds to rise from silence to full level
+    pub attack: f64,
+    /// seconds to fall from full level to `sustain`
+    pub decay: f64,
+    /// the level held while the key is down, from 0 to 1
+    pub sustain: f64,
+    /// seconds to fall to silence once the key is released
+    pub release: f64,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope {
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.2,
+        }
+    }
+}
+
+impl Envelope {
+    /// The level `t` seconds after the key went down, for a key held for
+    /// `held` seconds.
+    pub fn level(&self, t: f64, held: f64) -> f64 {
+        let pressed = |t: f64| {
+            if t < self.attack {
+                t / self.attack
+            } else if t < self.attack + self.decay {
+                1.0 - (1.0 - self.sustain) * (t - self.attack) / self.decay
+            } else {
+                self.sustain
+            }
+        };
+        if t < held {
+            pressed(t)
+        } else if t < held + self.release {
+            pressed(held) * (1.0 - (t - held) / self.release)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The waveform a General MIDI program is played with, by instrument
+/// family.
+pub fn waveform_for_program(program: u8) -> Waveform {
+    match program / 8 {
+        // organs and synth leads
+        2 | 10 => Waveform::Square,
+        // guitars, basses, strings, ensembles, brass and reeds
+        3..=8 => Waveform::Sawtooth,
+        // pipes
+        9 => Waveform::Sine,
+        // pianos, chromatic percussion, pads and everything else
+        _ => Waveform::Triangle,
+    }
+}
+
+/// The frequency in Hz of MIDI key `key`, A4 (key 69) being 440 Hz.
+pub fn key_frequency(key: u8) -> f64 {
+    440.0 * 2f64.powf((key as f64 - 69.0) / 12.0)
+}
+
+/// A note found in the timeline.
+#[derive(Debug, Clone, PartialEq)]
+struct Note {
+    start: f64,
+    end: f64,
+    key: u8,
+    velocity: u8,
+    waveform: Waveform,
+}
+
+/// A polyphonic synthesizer playing every note of a MIDI file on simple
+/// oscillators. Program changes pick each channel's waveform and the drum
+/// channel plays noise; pitch bends and controllers are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Synth {
+    pub sample_rate: u32,
+    pub envelope: Envelope,
+    /// the peak level of a single note at full velocity
+    pub gain: f64,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Synth {
+        Synth {
+            sample_rate,
+            envelope: Envelope::default(),
+            gain: 0.25,
+        }
+    }
+
+    /// Pairs every note on with the note off releasing it. Notes still
+    /// held at the end of the file are released there.
+    fn notes(smf: &Smf) -> Vec<Note> {
+        let timeline = smf.timeline();
+        let end = timeline.last().map_or(0.0, |event| event.seconds);
+        let mut programs = [0u8; 16];
+        // notes sounding on each channel and key, oldest first
+        let mut held: Vec<(u8, Note)> = Vec::new();
+        let mut notes = Vec::new();
+        for event in &timeline {
+            let (channel, key, velocity) = match event.kind {
+                EventKind::ProgramChange { channel, program } => {
+                    programs[channel as usize & 0x0f] = program;
+                    continue;
+                }
+                EventKind::NoteOn {
+                    channel,
+                    key,
+                    velocity,
+                } => (channel, key, velocity),
+                EventKind::NoteOff { channel, key, .. } => (channel, key, 0),
+                _ => continue,
+            };
+            if velocity == 0 {
+                let sounding = held
+                    .iter()
+                    .position(|(c, note)| *c == channel && note.key == key);
+                if let Some(n) = sounding {
+                    let (_, mut note) = held.remove(n);
+                    note.end = event.seconds;
+                    notes.push(note);
+                }
+                continue;
+            }
+            let waveform = if channel == DRUM_CHANNEL {
+                Waveform::Noise
+            } else {
+                waveform_for_program(programs[channel as usize & 0x0f])
+            };
+            held.push((
+                channel,
+                Note {
+                    start: event.seconds,
+                    end,
+                    key,
+                    velocity,
+                    waveform,
+                },
+            ));
+        }
+        notes.extend(held.into_iter().map(|(_, note)| note));
+        notes
+    }
+
+    /// Plays `smf` into mono samples, scaled down if the notes together
+    /// would clip.
+    pub fn render(&self, smf: &Smf) -> Vec<f64> {
+        let rate = self.sample_rate as f64;
+        let notes = Synth::notes(smf);
+        let length = notes
+            .iter()
+            .map(|note| note.end + self.envelope.release)
+            .fold(0.0, f64::max);
+        let mut signal = vec![0.0; (length * rate).ceil() as usize];
+        for note in &notes {
+            let mut oscillator =
+                Oscillator::new(note.waveform, key_frequency(note.key), self.sample_rate);
+            oscillator.amplitude = self.gain * note.velocity as f64 / 127.0;
+            let held = note.end - note.start;
+            let first = (note.start * rate).round() as usize;
+            let last = ((note.end + self.envelope.release) * rate).ceil() as usize;
+            for (n, sample) in signal.iter_mut().enumerate().take(last).skip(first) {
+                let t = n as f64 / rate - note.start;
+                *sample += self.envelope.level(t.max(0.0), held) * oscillator.next().unwrap();
+            }
+        }
+        let peak = signal.iter().fold(0.0, |peak: f64, x| peak.max(x.abs()));
+        if peak > 1.0 {
+            for x in signal.iter_mut() {
+                *x /= peak;
+            }
+        }
+        signal
+    }
+
+    /// Plays `smf` into a mono 16 bit WAV, ready for `WAV::write`.
+    pub fn render_wav(&self, smf: &Smf) -> Result<WAV, WavError> {
+        let signal = Samples::from_f64(SampleFormat::I16, &self.render(smf));
+        WAV::new(self.sample_rate, 1, signal)
+    }
+}
+
+#[cfg(test)]
+mod synth_test {
+    use super::{key_frequency, Envelope, Synth};
+    use crate::io::midi::{Event, EventKind, Smf, Timing};
+
+    fn note(tick: u64, channel: u8, key: u8, velocity: u8) -> Event {
+        Event {
+            tick,
+            kind: EventKind::NoteOn {
+                channel,
+                key,
+                velocity,
+            },
+        }
+    }
+
+    #[test]
+    fn envelope() {
+        let envelope = Envelope {
+            attack: 1.0,
+            decay: 1.0,
+            sustain: 0.5,
+            release: 2.0,
+        };
+        assert_eq!(envelope.level(0.5, 10.0), 0.5);
+        assert_eq!(envelope.level(1.5, 10.0), 0.75);
+        assert_eq!(envelope.level(5.0, 10.0), 0.5);
+        assert_eq!(envelope.level(11.0, 10.0), 0.25);
+        assert_eq!(envelope.level(12.0, 10.0), 0.0);
+        // released during the attack
+        assert_eq!(envelope.level(0.5, 0.5), 0.5);
+        assert_eq!(envelope.level(1.5, 0.5), 0.25);
+        assert_eq!(key_frequency(69), 440.0);
+        assert_eq!(key_frequency(81), 880.0);
+    }
+
+    #[test]
+    fn renders_notes_in_time() {
+        // 96 ticks a quarter note at the default 120 bpm: 0.5 s a beat
+        let mut smf = Smf::new(Timing::Metrical(96));
+        smf.tracks[0] = vec![
+            note(96, 0, 69, 127),
+            note(192, 0, 69, 0),
+            note(192, 9, 38, 100),
+            note(288, 9, 38, 0),
+        ];
+        let synth = Synth::new(8000);
+        let wav = synth.render_wav(&smf).unwrap();
+        assert_eq!(wav.nchannels(), 1);
+        assert_eq!(wav.fmt_header.sample_rate, 8000);
+
+        let signal = synth.render(&smf);
+        // the drum is released at 1.5 s
+        assert_eq!(signal.len(), 13600);
+        let loudest = |from: f64, to: f64| {
+            signal[(from * 8000.0) as usize..(to * 8000.0) as usize]
+                .iter()
+                .fold(0.0, |peak: f64, x| peak.max(x.abs()))
+        };
+        assert_eq!(loudest(0.0, 0.5), 0.0);
+        assert!(loudest(0.6, 0.9) > 0.1);
+        assert!(loudest(0.6, 0.9) <= synth.gain);
+        assert!(loudest(1.1, 1.4) > 0.0);
+    }
+}
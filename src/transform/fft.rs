@@ -0,0 +1,328 @@
+use num::traits::{Float, FloatConst, NumAssign};
+use num::Complex;
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use super::real::{ComplexToReal, RealToComplex};
+
+/// A floating point type transforms can run in: `f32` for speed or `f64`
+/// for precision.
+pub trait FftFloat: Float + FloatConst + NumAssign + Debug + Send + Sync + 'static {}
+
+impl<T: Float + FloatConst + NumAssign + Debug + Send + Sync + 'static> FftFloat for T {}
+
+pub(crate) fn cast<T: FftFloat>(n: usize) -> T {
+    T::from(n).unwrap()
+}
+
+/// Which way a plan transforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FftDirection {
+    Forward,
+    /// unscaled: a forward then inverse transform multiplies by the length
+    Inverse,
+}
+
+impl FftDirection {
+    /// The sign of the exponent in the transform's twiddle factors.
+    pub(crate) fn sign<T: FftFloat>(self) -> T {
+        match self {
+            FftDirection::Forward => -T::one(),
+            FftDirection::Inverse => T::one(),
+        }
+    }
+}
+
+/// Reorders `buffer` so each element sits at the bit-reversed index of where
+/// it started.
+fn bit_reverse<T>(buffer: &mut [Complex<T>]) {
+    let n = buffer.len();
+    let mut j = 0;
+    for k in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if k < j {
+            buffer.swap(k, j);
+        }
+    }
+}
+
+/// The iterative Cooley-Tukey transform of a buffer whose length is a power
+/// of two, in place. `twiddles` holds `exp(±2πik/n)` for `k` below `n / 2`.
+fn radix2<T: FftFloat>(buffer: &mut [Complex<T>], twiddles: &[Complex<T>]) {
+    let n = buffer.len();
+    bit_reverse(buffer);
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        let stride = n / len;
+        for chunk in buffer.chunks_exact_mut(len) {
+            let (evens, odds) = chunk.split_at_mut(half);
+            for (k, (even, odd)) in evens.iter_mut().zip(odds.iter_mut()).enumerate() {
+                let t = twiddles[k * stride] * *odd;
+                *odd = *even - t;
+                *even += t;
+            }
+        }
+        len *= 2;
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Algorithm<T> {
+    /// lengths 0 and 1 are their own transform
+    Identity,
+    Radix2 {
+        twiddles: Vec<Complex<T>>,
+    },
+    /// the transform rewritten as a convolution with a chirp, which is done
+    /// with forward radix-2 transforms zero padded to a power of two
+    Bluestein {
+        /// forward twiddles for the padded length
+        twiddles: Vec<Complex<T>>,
+        /// `exp(±πik²/n)`
+        chirp: Vec<Complex<T>>,
+        /// the transformed conjugate chirp, scaled by the padded length
+        kernel: Vec<Complex<T>>,
+        /// space for the padded signal
+        scratch: Vec<Complex<T>>,
+    },
+}
+
+/// A transform for one length and direction with everything it needs
+/// computed up front, so running it neither allocates nor calls trig
+/// functions. Powers of two use radix-2 directly; other lengths go through
+/// Bluestein's algorithm.
+#[derive(Debug, Clone)]
+pub struct FftPlan<T: FftFloat = f64> {
+    len: usize,
+    direction: FftDirection,
+    algorithm: Algorithm<T>,
+}
+
+impl<T: FftFloat> FftPlan<T> {
+    pub fn new(len: usize, direction: FftDirection) -> FftPlan<T> {
+        let sign: T = direction.sign();
+        let twiddles = |n: usize, sign: T| -> Vec<Complex<T>> {
+            (0..n / 2)
+                .map(|k| Complex::from_polar(T::one(), sign * T::TAU() * cast(k) / cast(n)))
+                .collect()
+        };
+        let algorithm = if len <= 1 {
+            Algorithm::Identity
+        } else if len.is_power_of_two() {
+            Algorithm::Radix2 {
+                twiddles: twiddles(len, sign),
+            }
+        } else {
+            let m = (2 * len - 1).next_power_of_two();
+            let twiddles = twiddles(m, -T::one());
+            // k² reduced modulo 2n so large k stay accurate
+            let chirp: Vec<Complex<T>> = (0..len)
+                .map(|k| {
+                    let k2 = (k * k) % (2 * len);
+                    Complex::from_polar(T::one(), sign * T::PI() * cast(k2) / cast(len))
+                })
+                .collect();
+            let mut kernel = vec![Complex::new(T::zero(), T::zero()); m];
+            kernel[0] = chirp[0].conj();
+            for k in 1..len {
+                kernel[k] = chirp[k].conj();
+                kernel[m - k] = chirp[k].conj();
+            }
+            radix2(&mut kernel, &twiddles);
+            for x in kernel.iter_mut() {
+                *x /= cast::<T>(m);
+            }
+            Algorithm::Bluestein {
+                twiddles,
+                chirp,
+                kernel,
+                scratch: vec![Complex::new(T::zero(), T::zero()); m],
+            }
+        };
+        FftPlan {
+            len,
+            direction,
+            algorithm,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn direction(&self) -> FftDirection {
+        self.direction
+    }
+
+    /// Transforms `buffer` in place.
+    ///
+    /// Panics if `buffer` is not the length the plan was made for.
+    pub fn process(&mut self, buffer: &mut [Complex<T>]) {
+        assert_eq!(
+            buffer.len(),
+            self.len,
+            "buffer length does not match the plan"
+        );
+        match &mut self.algorithm {
+            Algorithm::Identity => {}
+            Algorithm::Radix2 { twiddles } => radix2(buffer, twiddles),
+            Algorithm::Bluestein {
+                twiddles,
+                chirp,
+                kernel,
+                scratch,
+            } => {
+                for (n, a) in scratch.iter_mut().enumerate() {
+                    *a = match (buffer.get(n), chirp.get(n)) {
+                        (Some(x), Some(w)) => x * w,
+                        _ => Complex::new(T::zero(), T::zero()),
+                    };
+                }
+                radix2(scratch, twiddles);
+                for (a, b) in scratch.iter_mut().zip(kernel.iter()) {
+                    // conjugated so the forward transform below runs in reverse
+                    *a = (*a * b).conj();
+                }
+                radix2(scratch, twiddles);
+                for ((x, a), w) in buffer.iter_mut().zip(scratch.iter()).zip(chirp.iter()) {
+                    *x = a.conj() * w;
+                }
+            }
+        }
+    }
+}
+
+/// Hands out plans, making each length and direction once.
+#[derive(Debug, Clone)]
+pub struct FftPlanner<T: FftFloat = f64> {
+    plans: HashMap<(usize, FftDirection), FftPlan<T>>,
+    real_forward: HashMap<usize, RealToComplex<T>>,
+    real_inverse: HashMap<usize, ComplexToReal<T>>,
+}
+
+impl<T: FftFloat> Default for FftPlanner<T> {
+    fn default() -> FftPlanner<T> {
+        FftPlanner {
+            plans: HashMap::new(),
+            real_forward: HashMap::new(),
+            real_inverse: HashMap::new(),
+        }
+    }
+}
+
+impl<T: FftFloat> FftPlanner<T> {
+    pub fn new() -> FftPlanner<T> {
+        FftPlanner::default()
+    }
+
+    pub fn plan(&mut self, len: usize, direction: FftDirection) -> &mut FftPlan<T> {
+        self.plans
+            .entry((len, direction))
+            .or_insert_with(|| FftPlan::new(len, direction))
+    }
+
+    pub fn plan_real_forward(&mut self, len: usize) -> &mut RealToComplex<T> {
+        self.real_forward
+            .entry(len)
+            .or_insert_with(|| RealToComplex::new(len))
+    }
+
+    pub fn plan_real_inverse(&mut self, len: usize) -> &mut ComplexToReal<T> {
+        self.real_inverse
+            .entry(len)
+            .or_insert_with(|| ComplexToReal::new(len))
+    }
+}
+
+/// The discrete Fourier transform of `buffer`, in place.
+pub fn forward<T: FftFloat>(buffer: &mut [Complex<T>]) {
+    FftPlan::new(buffer.len(), FftDirection::Forward).process(buffer);
+}
+
+/// The unscaled inverse transform of `buffer`, in place: running `forward`
+/// then `inverse` multiplies every element by the length.
+pub fn inverse<T: FftFloat>(buffer: &mut [Complex<T>]) {
+    FftPlan::new(buffer.len(), FftDirection::Inverse).process(buffer);
+}
+
+#[cfg(test)]
+mod fft_test {
+    use super::{forward, inverse, FftDirection, FftPlanner};
+    use num::Complex;
+    use std::f64::consts::PI;
+
+    fn dft(input: &[Complex<f64>]) -> Vec<Complex<f64>> {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input
+                    .iter()
+                    .enumerate()
+                    .map(|(j, x)| {
+                        x * Complex::from_polar(1.0, -2.0 * PI * (j * k % n) as f64 / n as f64)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn signal(n: usize) -> Vec<Complex<f64>> {
+        (0..n)
+            .map(|k| Complex::new((k as f64 * 0.37).sin() + 0.1 * k as f64, (k % 7) as f64))
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_dft() {
+        for &n in &[1, 2, 3, 5, 8, 12, 64, 97, 441, 1024] {
+            let input = signal(n);
+            let mut output = input.clone();
+            forward(&mut output);
+            for (a, b) in output.iter().zip(dft(&input)) {
+                assert!(
+                    (a - b).norm() < 1e-8 * n as f64,
+                    "n = {}: {} != {}",
+                    n,
+                    a,
+                    b
+                );
+            }
+
+            inverse(&mut output);
+            for (a, b) in output.iter().zip(&input) {
+                assert!((a / n as f64 - b).norm() < 1e-9, "n = {}", n);
+            }
+        }
+    }
+
+    #[test]
+    fn plans_are_reused() {
+        let mut planner = FftPlanner::new();
+        for &n in &[12, 16] {
+            let input = signal(n);
+            let mut expected = input.clone();
+            forward(&mut expected);
+            for _ in 0..2 {
+                let mut buffer = input.clone();
+                planner.plan(n, FftDirection::Forward).process(&mut buffer);
+                assert_eq!(buffer, expected);
+                planner.plan(n, FftDirection::Inverse).process(&mut buffer);
+                for (a, b) in buffer.iter().zip(&input) {
+                    assert!((a / n as f64 - b).norm() < 1e-9);
+                }
+            }
+        }
+        assert_eq!(planner.plan(12, FftDirection::Inverse).len(), 12);
+    }
+}
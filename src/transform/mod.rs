@@ -1,77 +1,106 @@
 use num::cast::ToPrimitive;
-use num::{Complex, Integer};
-
-use std::f64::consts::PI;
-
-#[allow(non_upper_case_globals)]
-const i: Complex<f64> = Complex::new(0.0, 1.0);
-
-fn calculate_kth_nth<I: Integer + ToPrimitive>(
-    x_n: &I,
-    n: usize,
-    n_samples: usize,
-    k: usize,
-) -> Complex<f64> {
-    let x_n = x_n.to_f64().unwrap();
-    let n = n.to_f64().unwrap();
-    let n_samples = n_samples.to_f64().unwrap();
-    let k = k.to_f64().unwrap();
-    let inner = 2.0 * PI * k * n / n_samples;
-    x_n * (inner.cos() - i * inner.sin())
+use num::Complex;
+
+pub mod fft;
+pub mod real;
+pub mod spectrum;
+pub mod stft;
+pub mod window;
+
+pub use fft::{FftDirection, FftFloat, FftPlan, FftPlanner};
+pub use real::{spectrum_len, ComplexToReal, RealToComplex};
+pub use spectrum::{Peak, Spectrum};
+pub use stft::{Spectrogram, Stft};
+pub use window::Window;
+
+/// Converts integer or float samples to the precision a transform runs in.
+fn to_float<T: FftFloat, I: ToPrimitive>(samples: &[I]) -> Vec<T> {
+    samples
+        .iter()
+        .map(|x| T::from(x.to_f64().unwrap()).unwrap())
+        .collect()
 }
 
-fn calculate_kth<I: Integer + ToPrimitive>(k: usize, samples: &Vec<I>) -> Complex<f64> {
-    let mut x_k = Complex::new(0.0, 0.0);
-    let n_samples = samples.len();
-    for (n, x_n) in samples.iter().enumerate() {
-        let tmp = calculate_kth_nth(x_n, n, n_samples, k);
-        x_k += tmp;
-    }
-    x_k
+/// The discrete Fourier transform of `samples`, of any length.
+pub fn fourier_transform<I: ToPrimitive>(samples: Vec<I>) -> Vec<Complex<f64>> {
+    fourier_transform_as(samples)
+}
+
+/// `fourier_transform` computed in, and returned at, the precision `T`.
+pub fn fourier_transform_as<T: FftFloat, I: ToPrimitive>(samples: Vec<I>) -> Vec<Complex<T>> {
+    let mut buffer: Vec<Complex<T>> = to_float(&samples)
+        .into_iter()
+        .map(|x| Complex::new(x, T::zero()))
+        .collect();
+    fft::forward(&mut buffer);
+    buffer
 }
 
-pub fn fourier_transform<I: Integer + ToPrimitive>(samples: Vec<I>) -> Vec<Complex<f64>> {
-    let mut transformed_samples: Vec<Complex<f64>> = Vec::new();
-    let n_samples = samples.len();
-    for k in 0..n_samples {
-        let x_k = calculate_kth(k, &samples);
-        transformed_samples.push(x_k);
+/// The inverse of `fourier_transform`, scaled so the two round trip.
+pub fn inverse_fourier_transform<T: FftFloat>(samples: Vec<Complex<T>>) -> Vec<Complex<T>> {
+    let mut buffer = samples;
+    fft::inverse(&mut buffer);
+    let n_samples = fft::cast::<T>(buffer.len());
+    for x in &mut buffer {
+        *x /= n_samples;
     }
-    transformed_samples
+    buffer
 }
 
-fn calculate_kth_nth_inverse(
-    x_n: &Complex<f64>,
-    n: usize,
-    n_samples: usize,
-    k: usize,
-) -> Complex<f64> {
-    //let x_n = *x_n as Complex<f64>;
-    let n = n as f64;
-    let n_samples = n_samples as f64;
-    let k = k as f64;
-    let inner = 2.0 * PI * k * n / n_samples;
-    x_n * (inner.cos() + i * inner.sin())
+/// The first `samples.len() / 2 + 1` bins of `fourier_transform`, the rest
+/// being their mirrored conjugates.
+pub fn real_fourier_transform<I: ToPrimitive>(samples: Vec<I>) -> Vec<Complex<f64>> {
+    real_fourier_transform_as(samples)
 }
 
-fn calculate_kth_inverse(k: usize, samples: &Vec<Complex<f64>>) -> Complex<f64> {
-    let mut x_k = Complex::new(0.0, 0.0);
-    let n_samples = samples.len();
-    for (n, x_n) in samples.iter().enumerate() {
-        let tmp = calculate_kth_nth_inverse(x_n, n, n_samples, k);
-        x_k += tmp;
-    }
-    x_k
+/// `real_fourier_transform` computed in, and returned at, the precision `T`.
+pub fn real_fourier_transform_as<T: FftFloat, I: ToPrimitive>(samples: Vec<I>) -> Vec<Complex<T>> {
+    let samples: Vec<T> = to_float(&samples);
+    let mut spectrum = vec![Complex::new(T::zero(), T::zero()); spectrum_len(samples.len())];
+    RealToComplex::new(samples.len()).process(&samples, &mut spectrum);
+    spectrum
+}
+
+/// The half spectrum of `samples` tapered by `window`, calibrated so each
+/// bin's magnitude is the amplitude of the tone in it.
+pub fn windowed_fourier_transform<I: ToPrimitive>(
+    samples: Vec<I>,
+    window: Window,
+) -> Vec<Complex<f64>> {
+    windowed_fourier_transform_as(samples, window)
+}
+
+/// `windowed_fourier_transform` computed in, and returned at, the precision
+/// `T`.
+pub fn windowed_fourier_transform_as<T: FftFloat, I: ToPrimitive>(
+    samples: Vec<I>,
+    window: Window,
+) -> Vec<Complex<T>> {
+    let coefficients = window.coefficients::<T>(samples.len());
+    let samples: Vec<T> = to_float::<T, I>(&samples)
+        .into_iter()
+        .zip(&coefficients)
+        .map(|(x, &w)| x * w)
+        .collect();
+    let mut spectrum = vec![Complex::new(T::zero(), T::zero()); spectrum_len(samples.len())];
+    RealToComplex::new(samples.len()).process(&samples, &mut spectrum);
+    window::calibrate(&mut spectrum, &coefficients);
+    spectrum
 }
 
-pub fn inverse_fourier_transform(samples: Vec<Complex<f64>>) -> Vec<Complex<f64>> {
-    let mut transformed_samples: Vec<Complex<f64>> = Vec::new();
-    let n_samples = samples.len();
-    for k in 0..n_samples {
-        let x_k = calculate_kth_inverse(k, &samples) / n_samples as f64;
-        transformed_samples.push(x_k);
+/// The inverse of `real_fourier_transform` for `len` samples, scaled so the
+/// two round trip.
+pub fn inverse_real_fourier_transform<T: FftFloat>(
+    spectrum: Vec<Complex<T>>,
+    len: usize,
+) -> Vec<T> {
+    let mut samples = vec![T::zero(); len];
+    ComplexToReal::new(len).process(&spectrum, &mut samples);
+    let n_samples = fft::cast::<T>(len);
+    for x in &mut samples {
+        *x /= n_samples;
     }
-    transformed_samples
+    samples
 }
 
 #[cfg(test)]
@@ -139,7 +168,9 @@ mod there_and_back_again {
 
 #[cfg(test)]
 mod ft_test {
-    use super::{fourier_transform, round_complex};
+    use super::{
+        fourier_transform, fourier_transform_as, inverse_fourier_transform, round_complex,
+    };
     use num::Complex;
 
     #[test]
@@ -207,6 +238,23 @@ mod ft_test {
         let result_i16 = fourier_transform(input_i16);
         assert_eq!(result_i64, result_i16);
     }
+
+    #[test]
+    fn floats_and_integers_equal() {
+        let input_f32: Vec<f32> = vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let input_i16: Vec<i16> = vec![0, 1, 0, 0, 0, 0, 0, 0];
+        assert_eq!(fourier_transform(input_f32), fourier_transform(input_i16));
+    }
+
+    #[test]
+    fn single_precision() {
+        let input: Vec<f64> = vec![0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let result: Vec<Complex<f32>> = fourier_transform_as(input);
+        assert_eq!(result[0], Complex::new(0.5, 0.0));
+        assert!((result[1] - Complex::new(0.353_553_4, -0.353_553_4)).norm() < 1e-6);
+        let back = inverse_fourier_transform(result);
+        assert!((back[1] - Complex::new(0.5, 0.0)).norm() < 1e-6);
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,209 @@
+use num::Complex;
+
+use super::fft::{cast, FftDirection, FftFloat, FftPlan};
+
+/// The number of bins in the spectrum of `len` real samples, the rest being
+/// the conjugates of these mirrored.
+pub fn spectrum_len(len: usize) -> usize {
+    len / 2 + 1
+}
+
+/// `exp(∓2πik/n)` for `k` up to `n / 2`, the sign following `direction`.
+fn twiddles<T: FftFloat>(n: usize, direction: FftDirection) -> Vec<Complex<T>> {
+    let sign: T = direction.sign();
+    (0..=n / 2)
+        .map(|k| Complex::from_polar(T::one(), sign * T::TAU() * cast(k) / cast(n)))
+        .collect()
+}
+
+/// A forward transform of real samples into the `len / 2 + 1` bins that are
+/// not redundant. Even lengths pack pairs of samples into a complex
+/// transform of half the length; odd lengths run a full complex transform.
+#[derive(Debug, Clone)]
+pub struct RealToComplex<T: FftFloat = f64> {
+    len: usize,
+    inner: FftPlan<T>,
+    twiddles: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: FftFloat> RealToComplex<T> {
+    pub fn new(len: usize) -> RealToComplex<T> {
+        let inner_len = if len.is_multiple_of(2) { len / 2 } else { len };
+        RealToComplex {
+            len,
+            inner: FftPlan::new(inner_len, FftDirection::Forward),
+            twiddles: twiddles(len, FftDirection::Forward),
+            scratch: vec![Complex::new(T::zero(), T::zero()); inner_len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Transforms `input` into `output`.
+    ///
+    /// Panics if `input` is not the length the plan was made for or `output`
+    /// is not `spectrum_len` of it.
+    pub fn process(&mut self, input: &[T], output: &mut [Complex<T>]) {
+        assert_eq!(
+            input.len(),
+            self.len,
+            "input length does not match the plan"
+        );
+        assert_eq!(
+            output.len(),
+            spectrum_len(self.len),
+            "output length does not match the plan"
+        );
+        if self.len % 2 == 1 {
+            for (z, &x) in self.scratch.iter_mut().zip(input) {
+                *z = Complex::new(x, T::zero());
+            }
+            self.inner.process(&mut self.scratch);
+            output.copy_from_slice(&self.scratch[..output.len()]);
+            return;
+        }
+        let half = self.len / 2;
+        if half == 0 {
+            output[0] = Complex::new(T::zero(), T::zero());
+            return;
+        }
+        for (z, pair) in self.scratch.iter_mut().zip(input.chunks_exact(2)) {
+            *z = Complex::new(pair[0], pair[1]);
+        }
+        self.inner.process(&mut self.scratch);
+        let half_one = cast::<T>(1) / cast(2);
+        for (k, x) in output.iter_mut().enumerate() {
+            let z = self.scratch[k % half];
+            let mirror = self.scratch[(half - k % half) % half].conj();
+            let even = (z + mirror) * half_one;
+            let odd = (z - mirror) * Complex::new(T::zero(), -half_one);
+            *x = even + self.twiddles[k] * odd;
+        }
+    }
+}
+
+/// The inverse of `RealToComplex`, unscaled like the complex inverse:
+/// transforming there and back multiplies every sample by the length. The
+/// imaginary parts of the bins that must be real, the first and for even
+/// lengths the last, are ignored.
+#[derive(Debug, Clone)]
+pub struct ComplexToReal<T: FftFloat = f64> {
+    len: usize,
+    inner: FftPlan<T>,
+    twiddles: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: FftFloat> ComplexToReal<T> {
+    pub fn new(len: usize) -> ComplexToReal<T> {
+        let inner_len = if len.is_multiple_of(2) { len / 2 } else { len };
+        ComplexToReal {
+            len,
+            inner: FftPlan::new(inner_len, FftDirection::Inverse),
+            twiddles: twiddles(len, FftDirection::Inverse),
+            scratch: vec![Complex::new(T::zero(), T::zero()); inner_len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Transforms `input` into `output`.
+    ///
+    /// Panics if `output` is not the length the plan was made for or `input`
+    /// is not `spectrum_len` of it.
+    pub fn process(&mut self, input: &[Complex<T>], output: &mut [T]) {
+        assert_eq!(
+            output.len(),
+            self.len,
+            "output length does not match the plan"
+        );
+        assert_eq!(
+            input.len(),
+            spectrum_len(self.len),
+            "input length does not match the plan"
+        );
+        let last = input.len() - 1;
+        if self.len % 2 == 1 {
+            for (k, z) in self.scratch.iter_mut().enumerate() {
+                *z = if k <= last {
+                    input[k]
+                } else {
+                    input[self.len - k].conj()
+                };
+            }
+            self.scratch[0].im = T::zero();
+            self.inner.process(&mut self.scratch);
+            for (x, z) in output.iter_mut().zip(&self.scratch) {
+                *x = z.re;
+            }
+            return;
+        }
+        let half = self.len / 2;
+        if half == 0 {
+            return;
+        }
+        let real = |k: usize| {
+            if k == 0 || k == last {
+                Complex::new(input[k].re, T::zero())
+            } else {
+                input[k]
+            }
+        };
+        for (k, z) in self.scratch.iter_mut().enumerate() {
+            let x = real(k);
+            let mirror = real(half - k).conj();
+            let even = x + mirror;
+            let odd = (x - mirror) * self.twiddles[k];
+            *z = even + Complex::<T>::i() * odd;
+        }
+        self.inner.process(&mut self.scratch);
+        for (pair, z) in output.chunks_exact_mut(2).zip(&self.scratch) {
+            pair[0] = z.re;
+            pair[1] = z.im;
+        }
+    }
+}
+
+#[cfg(test)]
+mod real_test {
+    use super::{spectrum_len, ComplexToReal, RealToComplex};
+    use crate::transform::fft;
+    use num::Complex;
+
+    #[test]
+    fn matches_the_complex_transform() {
+        for &n in &[1, 2, 3, 4, 7, 10, 16, 200, 441] {
+            let input: Vec<f64> = (0..n)
+                .map(|k| (k as f64 * 0.61).cos() + (k % 5) as f64 * 0.2)
+                .collect();
+            let mut expected: Vec<Complex<f64>> =
+                input.iter().map(|&x| Complex::new(x, 0.0)).collect();
+            fft::forward(&mut expected);
+
+            let mut spectrum = vec![Complex::new(0.0, 0.0); spectrum_len(n)];
+            RealToComplex::new(n).process(&input, &mut spectrum);
+            for (a, b) in spectrum.iter().zip(&expected) {
+                assert!((a - b).norm() < 1e-9, "n = {}: {} != {}", n, a, b);
+            }
+
+            let mut output = vec![0.0; n];
+            ComplexToReal::new(n).process(&spectrum, &mut output);
+            for (a, b) in output.iter().zip(&input) {
+                assert!((a / n as f64 - b).abs() < 1e-9, "n = {}", n);
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use num::cast::ToPrimitive;
+use num::Complex;
+
+use super::fft::FftFloat;
+use super::window::Window;
+use super::windowed_fourier_transform_as;
+
+/// A local maximum of a spectrum, located between bins by fitting a
+/// parabola through the log magnitudes of the highest bin and its two
+/// neighbours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    /// the fractional bin the peak falls on
+    pub bin: f64,
+    /// in Hz
+    pub frequency: f64,
+    /// the estimated amplitude at the peak
+    pub magnitude: f64,
+}
+
+/// The half spectrum of `len` real samples taken at `sample_rate`, which is
+/// what relates its bins to frequencies: bin `k` is centered on
+/// `k * sample_rate / len` Hz.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum<T: FftFloat = f64> {
+    pub sample_rate: u32,
+    /// the number of samples transformed
+    pub len: usize,
+    /// `len / 2 + 1` bins, from DC up to the Nyquist frequency
+    pub bins: Vec<Complex<T>>,
+}
+
+impl<T: FftFloat> Spectrum<T> {
+    /// The calibrated spectrum of `samples` tapered by `window`, as
+    /// `windowed_fourier_transform` computes it.
+    pub fn new<I: ToPrimitive>(samples: Vec<I>, sample_rate: u32, window: Window) -> Spectrum<T> {
+        let len = samples.len();
+        Spectrum {
+            sample_rate,
+            len,
+            bins: windowed_fourier_transform_as(samples, window),
+        }
+    }
+
+    /// The distance in Hz between the centers of neighbouring bins, or 0
+    /// for the spectrum of no samples.
+    pub fn bin_width(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        self.sample_rate as f64 / self.len as f64
+    }
+
+    /// The frequency in Hz at `bin`, which may be fractional.
+    pub fn frequency(&self, bin: f64) -> f64 {
+        bin * self.bin_width()
+    }
+
+    /// The fractional bin `frequency` Hz falls on.
+    pub fn bin(&self, frequency: f64) -> f64 {
+        frequency / self.bin_width()
+    }
+
+    /// The bin whose center is nearest `frequency` Hz, if the spectrum
+    /// reaches that high.
+    pub fn nearest_bin(&self, frequency: f64) -> Option<usize> {
+        let bin = self.bin(frequency).round();
+        if bin >= 0.0 && (bin as usize) < self.bins.len() {
+            Some(bin as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The frequency in Hz of the center of every bin.
+    pub fn frequencies(&self) -> Vec<f64> {
+        (0..self.bins.len())
+            .map(|k| self.frequency(k as f64))
+            .collect()
+    }
+
+    pub fn magnitudes(&self) -> Vec<T> {
+        self.bins.iter().map(|x| x.norm()).collect()
+    }
+
+    /// The peak refined from the maximum at bin `k`.
+    fn refine(&self, magnitudes: &[T], k: usize) -> Peak {
+        let at = |k: usize| magnitudes[k].to_f64().unwrap();
+        let (offset, magnitude) = if k == 0 || k + 1 == magnitudes.len() {
+            (0.0, at(k))
+        } else {
+            let floor = f64::MIN_POSITIVE;
+            let (a, b, c) = (
+                at(k - 1).max(floor).ln(),
+                at(k).max(floor).ln(),
+                at(k + 1).max(floor).ln(),
+            );
+            let curvature = a - 2.0 * b + c;
+            if curvature >= 0.0 {
+                (0.0, at(k))
+            } else {
+                let p = 0.5 * (a - c) / curvature;
+                (p, (b - 0.25 * (a - c) * p).exp())
+            }
+        };
+        let bin = k as f64 + offset;
+        Peak {
+            bin,
+            frequency: self.frequency(bin),
+            magnitude,
+        }
+    }
+
+    /// The loudest peak, if there were any samples. DC is ignored when
+    /// there are other bins.
+    pub fn peak(&self) -> Option<Peak> {
+        if self.len == 0 {
+            return None;
+        }
+        let magnitudes = self.magnitudes();
+        let first = if magnitudes.len() > 1 { 1 } else { 0 };
+        let (k, _) = magnitudes.iter().enumerate().skip(first).fold(
+            None,
+            |max: Option<(usize, T)>, (k, &x)| match max {
+                Some((_, y)) if y >= x => max,
+                _ => Some((k, x)),
+            },
+        )?;
+        Some(self.refine(&magnitudes, k))
+    }
+
+    /// Up to `count` of the loudest local maxima above DC, loudest first.
+    pub fn peaks(&self, count: usize) -> Vec<Peak> {
+        let magnitudes = self.magnitudes();
+        let zero = T::zero();
+        let mut peaks: Vec<Peak> = (1..magnitudes.len())
+            .filter(|&k| {
+                let x = magnitudes[k];
+                x > zero
+                    && x > magnitudes[k - 1]
+                    && magnitudes.get(k + 1).is_none_or(|&next| x >= next)
+            })
+            .map(|k| self.refine(&magnitudes, k))
+            .collect();
+        peaks.sort_by(|a, b| b.magnitude.partial_cmp(&a.magnitude).unwrap());
+        peaks.truncate(count);
+        peaks
+    }
+}
+
+#[cfg(test)]
+mod spectrum_test {
+    use super::Spectrum;
+    use crate::transform::Window;
+    use std::f64::consts::PI;
+
+    fn tone(frequency: f64, sample_rate: u32, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| 0.8 * (2.0 * PI * frequency * n as f64 / sample_rate as f64).sin())
+            .collect()
+    }
+
+    #[test]
+    fn bins_and_frequencies() {
+        // a tenth of a second at 44.1 kHz: bins 10 Hz apart
+        let spectrum: Spectrum = Spectrum::new(tone(100.0, 44100, 4410), 44100, Window::Hann);
+        assert_eq!(spectrum.bins.len(), 2206);
+        assert_eq!(spectrum.bin_width(), 10.0);
+        assert_eq!(spectrum.frequency(10.0), 100.0);
+        assert_eq!(spectrum.bin(1000.0), 100.0);
+        assert_eq!(spectrum.nearest_bin(104.0), Some(10));
+        assert_eq!(spectrum.nearest_bin(30000.0), None);
+        assert_eq!(spectrum.frequencies()[2205], 22050.0);
+        let peak = spectrum.peak().unwrap();
+        assert!((peak.frequency - 100.0).abs() < 1e-6);
+        assert!((peak.magnitude - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn between_bins() {
+        let spectrum: Spectrum = Spectrum::new(tone(1000.2, 44100, 4096), 44100, Window::Hann);
+        let peak = spectrum.peak().unwrap();
+        assert!((peak.frequency - 1000.2).abs() < 0.1, "{:?}", peak);
+
+        let mut signal = tone(440.0, 8000, 2048);
+        for (x, y) in signal.iter_mut().zip(tone(1234.5, 8000, 2048)) {
+            *x += y / 4.0;
+        }
+        let spectrum: Spectrum<f32> = Spectrum::new(signal, 8000, Window::BlackmanHarris);
+        let peaks = spectrum.peaks(2);
+        assert_eq!(peaks.len(), 2);
+        assert!((peaks[0].frequency - 440.0).abs() < 0.5);
+        assert!((peaks[1].frequency - 1234.5).abs() < 0.5);
+        assert!(peaks[0].magnitude > peaks[1].magnitude);
+    }
+
+    #[test]
+    fn no_samples() {
+        let spectrum: Spectrum = Spectrum::new(Vec::<f64>::new(), 44100, Window::Hann);
+        assert_eq!(spectrum.bin_width(), 0.0);
+        assert_eq!(spectrum.peak(), None);
+        assert!(spectrum.peaks(3).is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+use num::Complex;
+
+use std::ops::Range;
+use std::thread;
+
+use super::fft::{cast, FftFloat};
+use super::real::{spectrum_len, ComplexToReal, RealToComplex};
+use super::window::Window;
+
+/// The settings of a short-time Fourier transform: the signal is cut into
+/// overlapping frames, each tapered by `window` and transformed on its own.
+/// Frames are centered on multiples of `hop_size`, the signal being padded
+/// with silence at both ends, so the first frame is centered on the first
+/// sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stft {
+    /// samples in each frame
+    pub frame_size: usize,
+    /// samples between the centers of consecutive frames
+    pub hop_size: usize,
+    pub window: Window,
+}
+
+impl Stft {
+    /// Panics if either size is zero.
+    pub fn new(frame_size: usize, hop_size: usize, window: Window) -> Stft {
+        assert!(frame_size > 0, "frame size must not be zero");
+        assert!(hop_size > 0, "hop size must not be zero");
+        Stft {
+            frame_size,
+            hop_size,
+            window,
+        }
+    }
+
+    /// The number of frames covering `len` samples, the last centered at or
+    /// past the end.
+    pub fn nframes(&self, len: usize) -> usize {
+        len.div_ceil(self.hop_size) + 1
+    }
+
+    /// The half spectrum of every frame of `signal`.
+    pub fn process<T: FftFloat>(&self, signal: &[T]) -> Spectrogram<T> {
+        Spectrogram {
+            stft: *self,
+            len: signal.len(),
+            frames: self.frames(signal, 0..self.nframes(signal.len())),
+        }
+    }
+
+    /// `process` with the frames split into contiguous runs, one for each of
+    /// `nthreads` threads, and put back in order. Zero threads means one for
+    /// each core.
+    pub fn process_parallel<T: FftFloat>(&self, signal: &[T], nthreads: usize) -> Spectrogram<T> {
+        let nthreads = if nthreads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            nthreads
+        };
+        let nframes = self.nframes(signal.len());
+        let run = nframes.div_ceil(nthreads);
+        let frames = thread::scope(|scope| {
+            let handles: Vec<_> = (0..nframes)
+                .step_by(run)
+                .map(|start| {
+                    let frames = start..(start + run).min(nframes);
+                    scope.spawn(move || self.frames(signal, frames))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        Spectrogram {
+            stft: *self,
+            len: signal.len(),
+            frames,
+        }
+    }
+
+    /// The half spectra of the frames numbered `frames`.
+    fn frames<T: FftFloat>(&self, signal: &[T], frames: Range<usize>) -> Vec<Vec<Complex<T>>> {
+        let taper = self.window.coefficients::<T>(self.frame_size);
+        let mut plan = RealToComplex::new(self.frame_size);
+        let mut samples = vec![T::zero(); self.frame_size];
+        let offset = self.frame_size / 2;
+        frames
+            .map(|t| {
+                for (n, (x, w)) in samples.iter_mut().zip(&taper).enumerate() {
+                    // the index into the signal before padding
+                    *x = (t * self.hop_size + n)
+                        .checked_sub(offset)
+                        .and_then(|n| signal.get(n))
+                        .map_or(T::zero(), |&x| x * *w);
+                }
+                let mut spectrum =
+                    vec![Complex::new(T::zero(), T::zero()); spectrum_len(self.frame_size)];
+                plan.process(&samples, &mut spectrum);
+                spectrum
+            })
+            .collect()
+    }
+}
+
+/// The output of an `Stft`: a time × frequency matrix of complex bins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram<T: FftFloat = f64> {
+    pub stft: Stft,
+    /// the length of the signal transformed
+    pub len: usize,
+    /// the half spectrum of each frame, `frame_size / 2 + 1` bins long
+    pub frames: Vec<Vec<Complex<T>>>,
+}
+
+impl<T: FftFloat> Spectrogram<T> {
+    /// The factor turning each bin's magnitude into the amplitude of the
+    /// tone in it, as `window::calibrate` does.
+    fn calibration(&self) -> Vec<T> {
+        let frame_size = self.stft.frame_size;
+        let sum = self
+            .stft
+            .window
+            .coefficients::<T>(frame_size)
+            .into_iter()
+            .fold(T::zero(), |sum, w| sum + w);
+        (0..spectrum_len(frame_size))
+            .map(|k| {
+                if k == 0 || 2 * k == frame_size {
+                    T::one() / sum
+                } else {
+                    cast::<T>(2) / sum
+                }
+            })
+            .collect()
+    }
+
+    /// The amplitude of each bin of each frame, calibrated so a full scale
+    /// sine reads 1.
+    pub fn magnitudes(&self) -> Vec<Vec<T>> {
+        let calibration = self.calibration();
+        self.frames
+            .iter()
+            .map(|frame| {
+                frame
+                    .iter()
+                    .zip(&calibration)
+                    .map(|(x, &c)| x.norm() * c)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The square of `magnitudes`.
+    pub fn power(&self) -> Vec<Vec<T>> {
+        self.magnitudes()
+            .into_iter()
+            .map(|frame| frame.into_iter().map(|x| x * x).collect())
+            .collect()
+    }
+
+    /// `magnitudes` in decibels relative to full scale, no lower than
+    /// `floor`, which keeps silence finite.
+    pub fn decibels(&self, floor: T) -> Vec<Vec<T>> {
+        let twenty = cast::<T>(20);
+        self.magnitudes()
+            .into_iter()
+            .map(|frame| {
+                frame
+                    .into_iter()
+                    .map(|x| (twenty * x.log10()).max(floor))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Resynthesizes the signal by overlap-adding the inverse transform of
+    /// every frame, tapered by the window again and divided by the sum of
+    /// the squared windows over each sample. An unmodified spectrogram gives
+    /// back the signal it came from wherever some frame's window is not zero.
+    pub fn inverse(&self) -> Vec<T> {
+        let Stft {
+            frame_size,
+            hop_size,
+            window,
+        } = self.stft;
+        let taper = window.coefficients::<T>(frame_size);
+        let mut plan = ComplexToReal::new(frame_size);
+        let mut samples = vec![T::zero(); frame_size];
+        let padded = (self.frames.len().max(1) - 1) * hop_size + frame_size;
+        let mut signal = vec![T::zero(); padded];
+        let mut weights = vec![T::zero(); padded];
+        let scale = T::one() / cast(frame_size);
+        for (t, frame) in self.frames.iter().enumerate() {
+            plan.process(frame, &mut samples);
+            let start = t * hop_size;
+            for (n, (&x, &w)) in samples.iter().zip(&taper).enumerate() {
+                signal[start + n] += x * scale * w;
+                weights[start + n] += w * w;
+            }
+        }
+        let tiny = cast::<T>(1) / cast(1_000_000_000);
+        signal
+            .into_iter()
+            .zip(weights)
+            .skip(frame_size / 2)
+            .take(self.len)
+            .map(|(x, w)| if w > tiny { x / w } else { T::zero() })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod stft_test {
+    use super::Stft;
+    use crate::io::sample::{SampleFormat, Samples};
+    use crate::io::wav::WAV;
+    use crate::transform::Window;
+    use num::Complex;
+    use std::f64::consts::PI;
+
+    fn tones(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| {
+                0.5 * (2.0 * PI * 16.0 * n as f64 / 256.0).sin()
+                    + 0.25 * (2.0 * PI * 96.0 * n as f64 / 256.0).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn magnitudes() {
+        let stft = Stft::new(256, 64, Window::Hann);
+        let spectrogram = stft.process(&tones(1000));
+        assert_eq!(spectrogram.frames.len(), 17);
+        let magnitudes = spectrogram.magnitudes();
+        assert_eq!(magnitudes[8].len(), 129);
+        assert!((magnitudes[8][16] - 0.5).abs() < 1e-9);
+        assert!((magnitudes[8][96] - 0.25).abs() < 1e-9);
+        assert!((spectrogram.power()[8][16] - 0.25).abs() < 1e-9);
+        let decibels = spectrogram.decibels(-120.0);
+        assert!((decibels[8][96] + 12.041).abs() < 1e-3);
+        assert!(decibels[8][50] >= -120.0);
+    }
+
+    #[test]
+    fn there_and_back_again() {
+        for &(window, frame_size, hop_size) in &[
+            (Window::Hann, 256, 64),
+            (Window::Hamming, 100, 50),
+            (Window::Rectangular, 128, 128),
+            (Window::BlackmanHarris, 255, 32),
+        ] {
+            let signal = tones(1000);
+            let spectrogram = Stft::new(frame_size, hop_size, window).process(&signal);
+            let output = spectrogram.inverse();
+            assert_eq!(output.len(), signal.len());
+            for (a, b) in output.iter().zip(&signal) {
+                assert!((a - b).abs() < 1e-9, "{:?}: {} != {}", window, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let signal = tones(5000);
+        let stft = Stft::new(256, 64, Window::Hann);
+        let serial = stft.process(&signal);
+        for &nthreads in &[0, 1, 3, 8, 100] {
+            assert_eq!(stft.process_parallel(&signal, nthreads), serial);
+        }
+        let empty = stft.process_parallel::<f64>(&[], 4);
+        assert_eq!(empty.frames.len(), 1);
+    }
+
+    #[test]
+    fn filtered_resynthesis() {
+        let mut spectrogram = Stft::new(256, 64, Window::Hann).process(&tones(2048));
+        // drop the upper tone
+        for frame in &mut spectrogram.frames {
+            for x in frame.iter_mut().skip(64) {
+                *x = Complex::new(0.0, 0.0);
+            }
+        }
+        let output = spectrogram.inverse();
+        let wav = WAV::new(8000, 1, Samples::from_f64(SampleFormat::I16, &output)).unwrap();
+        assert_eq!(wav.nframes(), 2048);
+        for (n, x) in output.iter().enumerate().skip(256).take(1024) {
+            let lower = 0.5 * (2.0 * PI * 16.0 * n as f64 / 256.0).sin();
+            assert!((x - lower).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use num::Complex;
+
+use std::f64::consts::PI;
+
+use super::fft::{cast, FftFloat};
+
+/// A taper applied to a block of samples before transforming it, trading
+/// frequency resolution for less leakage between bins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    /// no taper at all
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    /// the four term Blackman-Harris window, for very low sidelobes
+    BlackmanHarris,
+    /// for reading amplitudes accurately wherever a tone falls between bins
+    FlatTop,
+    /// a Kaiser window with shape parameter β: 0 is rectangular and larger
+    /// values widen the main lobe while lowering the sidelobes
+    Kaiser(f64),
+    /// a rectangle with the given fraction of its length, from 0 to 1, tapered
+    /// by a cosine half at each end
+    Tukey(f64),
+}
+
+/// The zeroth order modified Bessel function of the first kind, by its power
+/// series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-16 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// `Σ (-1)^k a_k cos(2πkx)`.
+fn cosine_sum(coefficients: &[f64], x: f64) -> f64 {
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (2.0 * PI * k as f64 * x).cos()
+        })
+        .sum()
+}
+
+impl Window {
+    /// The value at `x` of the way across the window, from 0 to 1.
+    fn at(self, x: f64) -> f64 {
+        match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => cosine_sum(&[0.5, 0.5], x),
+            Window::Hamming => cosine_sum(&[0.54, 0.46], x),
+            Window::Blackman => cosine_sum(&[0.42, 0.5, 0.08], x),
+            Window::BlackmanHarris => cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], x),
+            Window::FlatTop => cosine_sum(
+                &[
+                    0.215_578_95,
+                    0.416_631_58,
+                    0.277_263_158,
+                    0.083_578_947,
+                    0.006_947_368,
+                ],
+                x,
+            ),
+            Window::Kaiser(beta) => {
+                let r = 2.0 * x - 1.0;
+                bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+            }
+            Window::Tukey(alpha) => {
+                let edge = alpha.clamp(0.0, 1.0) / 2.0;
+                let from_edge = x.min(1.0 - x);
+                if from_edge < edge {
+                    0.5 * (1.0 - (PI * from_edge / edge).cos())
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+
+    /// The `len` coefficients of the window. They are periodic, as suits
+    /// spectral analysis: one past the last would equal the first.
+    pub fn coefficients<T: FftFloat>(self, len: usize) -> Vec<T> {
+        (0..len)
+            .map(|n| T::from(self.at(n as f64 / len as f64)).unwrap())
+            .collect()
+    }
+
+    /// Multiplies `samples` by the window as long as they are.
+    pub fn apply<T: FftFloat>(self, samples: &mut [T]) {
+        let coefficients = self.coefficients::<T>(samples.len());
+        for (x, w) in samples.iter_mut().zip(coefficients) {
+            *x *= w;
+        }
+    }
+
+    /// The mean of the window's coefficients: how much it scales the peak of
+    /// a tone.
+    pub fn coherent_gain(self, len: usize) -> f64 {
+        let coefficients = self.coefficients::<f64>(len);
+        coefficients.iter().sum::<f64>() / len as f64
+    }
+
+    /// The equivalent noise bandwidth in bins: how much wider than one bin a
+    /// rectangular filter passing the same noise power would be.
+    pub fn enbw(self, len: usize) -> f64 {
+        let coefficients = self.coefficients::<f64>(len);
+        let sum = coefficients.iter().sum::<f64>();
+        let sum_of_squares = coefficients.iter().map(|w| w * w).sum::<f64>();
+        len as f64 * sum_of_squares / (sum * sum)
+    }
+}
+
+/// Scales the half spectrum of samples windowed by `coefficients` so each
+/// bin's magnitude is the amplitude of the tone in it: a full scale sine
+/// reads 1 whichever window was used.
+pub fn calibrate<T: FftFloat>(spectrum: &mut [Complex<T>], coefficients: &[T]) {
+    let sum = coefficients.iter().fold(T::zero(), |sum, &w| sum + w);
+    if sum == T::zero() {
+        return;
+    }
+    let len = coefficients.len();
+    for (k, x) in spectrum.iter_mut().enumerate() {
+        // every bin but DC and Nyquist also holds its mirrored twin's energy
+        let single_sided = k != 0 && 2 * k != len;
+        let scale = if single_sided { cast::<T>(2) } else { T::one() };
+        *x = *x * scale / sum;
+    }
+}
+
+#[cfg(test)]
+mod window_test {
+    use super::Window;
+    use crate::transform::windowed_fourier_transform;
+    use std::f64::consts::PI;
+
+    fn tone(frequency: f64, amplitude: f64, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| amplitude * (2.0 * PI * frequency * n as f64 / len as f64).sin())
+            .collect()
+    }
+
+    #[test]
+    fn correction_factors() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-3;
+        assert!(close(Window::Rectangular.coherent_gain(256), 1.0));
+        assert!(close(Window::Rectangular.enbw(256), 1.0));
+        assert!(close(Window::Hann.coherent_gain(256), 0.5));
+        assert!(close(Window::Hann.enbw(256), 1.5));
+        assert!(close(Window::Hamming.coherent_gain(256), 0.54));
+        assert!(close(Window::Blackman.enbw(256), 1.727));
+        assert!(close(Window::BlackmanHarris.enbw(256), 2.004));
+        assert!(close(Window::FlatTop.enbw(256), 3.770));
+        assert!(close(Window::Kaiser(0.0).enbw(256), 1.0));
+        assert!(close(Window::Tukey(0.0).enbw(256), 1.0));
+        assert!(close(Window::Tukey(1.0).enbw(256), 1.5));
+        assert!(Window::Kaiser(8.6).enbw(256) > Window::Hann.enbw(256));
+
+        let hann = Window::Hann.coefficients::<f64>(4);
+        for (a, b) in hann.iter().zip(&[0.0, 0.5, 1.0, 0.5]) {
+            assert!(close(*a, *b));
+        }
+    }
+
+    #[test]
+    fn magnitudes_are_calibrated() {
+        for &window in &[Window::Rectangular, Window::Hann, Window::BlackmanHarris] {
+            let spectrum = windowed_fourier_transform(tone(32.0, 0.5, 256), window);
+            assert_eq!(spectrum.len(), 129);
+            assert!((spectrum[32].norm() - 0.5).abs() < 1e-9, "{:?}", window);
+        }
+        // halfway between bins the flat top still reads the amplitude, where
+        // the Hann window loses 15%
+        let peak = |window| {
+            windowed_fourier_transform(tone(32.5, 0.5, 256), window)
+                .iter()
+                .map(|x| x.norm())
+                .fold(0.0, f64::max)
+        };
+        assert!((peak(Window::FlatTop) - 0.5).abs() < 0.5 * 0.002);
+        assert!(peak(Window::Hann) < 0.5 * 0.86);
+    }
+}